@@ -1,43 +1,565 @@
 pub mod arguments {
-    use std::process::exit;
+    use crate::data_structures::data_structures::{
+        CountingKernel, InputFormat, PowValidation, ScoringMode, TopScore,
+    };
+    use crate::errors::errors::Error;
+    use crate::features::derived::FEATURES;
+    use crate::features::pow::expected_hashes;
+    use crate::features::target::{parse_target, parse_target_groups, Target};
+    use crate::generator::mining::{
+        parse_strategy, MiningParameters, NonceStrategy, MAX_EXPECTED_HASHES,
+    };
+    use crate::generator::synthetic::{parse_planted, PlantedDependency};
+    use crate::header::layout::{parse_bit_mask, HEADER_BITS};
+    use std::fmt::Display;
+    use std::str::FromStr;
 
-    pub fn parse_config(args: &[String]) -> (u16, usize, String, String, u16, u16, usize, f32) {
-        if args.len() < 8 {
+    #[derive(Debug, Clone)]
+    pub struct Config {
+        pub tuple_size: u16,
+        pub slice_size: usize,
+        pub filename: String,
+        pub nonce_filename: String,
+        pub start_bit: u16,
+        pub end_bit: u16,
+        pub sample_threshold: usize,
+        pub info_threshold: f32,
+        pub min_bit_entropy: f32,
+        pub include_bits: Option<Vec<u16>>,
+        pub exclude_bits: Vec<u16>,
+        pub tuples_filename: Option<String>,
+        pub features: Vec<String>,
+        pub target: Target,
+        pub target_groups: Option<Vec<Vec<u16>>>,
+        pub target_group_size: u16,
+        pub mode: ScoringMode,
+        pub max_p_value: f64,
+        pub top_k: Option<usize>,
+        pub top_k_per_target: Option<usize>,
+        pub top_by: TopScore,
+        pub kernel: CountingKernel,
+        pub input_format: InputFormat,
+        pub validate_pow: Option<PowValidation>,
+        pub checkpoint_filename: String,
+        pub resume_filename: Option<String>,
+        pub progress_filename: Option<String>,
+        pub progress_interval: u64,
+        pub dry_run: bool,
+        // Rows per chunk when streaming the hashes file instead of loading it
+        pub chunk_rows: Option<usize>,
+        // Worker threads scoring the batches
+        pub threads: usize,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct GenerateConfig {
+        pub output_prefix: String,
+        pub rows: usize,
+        pub header_bits: u16,
+        pub nonce_bits: u16,
+        pub seed: u64,
+        pub planted: Vec<PlantedDependency>,
+    }
+
+    pub fn parse_config(args: &[String]) -> Result<Config, Error> {
+        if args.len() < 9 {
             show_usage();
-            exit(0);
-        }
-
-        let tuple_size: u16 = (args[1]).parse::<u16>().unwrap();
-        let slice_size: usize = (args[2]).parse::<usize>().unwrap();
-        let filename: &String = &args[3];
-        let nonce_filename: &String = &args[4];
-        let bit_start: u16 = (args[5]).parse::<u16>().unwrap();
-        let bit_end: u16 = (args[6]).parse::<u16>().unwrap();
-        let samples_threshold: usize = (args[7]).parse::<usize>().unwrap();
-        let info_threshold: f32 = (args[8]).parse::<f32>().unwrap();
-        (
-            tuple_size,
-            slice_size,
-            filename.to_string(),
-            nonce_filename.to_string(),
-            bit_start,
-            bit_end,
-            samples_threshold,
-            info_threshold,
-        )
+            return Err(missing_arguments(8, args.len() - 1));
+        }
+
+        let mut config = Config {
+            tuple_size: parse_value::<u16>("<tuple_size>", &args[1])?,
+            slice_size: parse_value::<usize>("<slice_size>", &args[2])?,
+            filename: args[3].to_string(),
+            nonce_filename: args[4].to_string(),
+            start_bit: parse_value::<u16>("<start bit>", &args[5])?,
+            end_bit: parse_value::<u16>("<end bit>", &args[6])?,
+            sample_threshold: parse_value::<usize>("<sample threshold>", &args[7])?,
+            info_threshold: parse_value::<f32>("<information threshold>", &args[8])?,
+            min_bit_entropy: 0.0,
+            include_bits: None,
+            exclude_bits: vec![],
+            tuples_filename: None,
+            features: vec![],
+            target: Target::Nonce,
+            target_groups: None,
+            target_group_size: 1,
+            mode: ScoringMode::Table,
+            max_p_value: 0.001,
+            top_k: None,
+            top_k_per_target: None,
+            top_by: TopScore::MutualInformation,
+            kernel: CountingKernel::Auto,
+            input_format: InputFormat::Bin,
+            validate_pow: None,
+            checkpoint_filename: "checkpoint.json".to_string(),
+            resume_filename: None,
+            progress_filename: None,
+            progress_interval: 10,
+            dry_run: false,
+            chunk_rows: None,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        };
+
+        let mut include_spec = None;
+        let mut options = args[9..].iter();
+        while let Some(option) = options.next() {
+            // Flags, the only options without a value
+            if option == "--dry-run" {
+                config.dry_run = true;
+                continue;
+            }
+            let value = match options.next() {
+                Some(value) => value,
+                None => {
+                    show_usage();
+                    return Err(Error::Usage(format!("Missing value for option {}", option)));
+                }
+            };
+            match option.as_str() {
+                "--min-bit-entropy" => config.min_bit_entropy = parse_value::<f32>(option, value)?,
+                "--include" => {
+                    config.include_bits = Some(parse_mask_option(option, value)?);
+                    include_spec = Some(value);
+                }
+                "--exclude" => config.exclude_bits = parse_mask_option(option, value)?,
+                "--tuples" => config.tuples_filename = Some(value.to_string()),
+                "--features" => config.features = parse_features(option, value)?,
+                "--target-bits" => {
+                    config.target_groups = match parse_target_groups(value) {
+                        Ok(groups) => Some(groups),
+                        Err(e) => return Err(invalid_value(option, e)),
+                    }
+                }
+                "--target-group-size" => {
+                    config.target_group_size = parse_value::<u16>(option, value)?
+                }
+                "--mode" => {
+                    config.mode = match value.as_str() {
+                        "table" => ScoringMode::Table,
+                        "parity" => ScoringMode::Parity,
+                        _ => return Err(invalid_value(option, value)),
+                    }
+                }
+                "--max-p-value" => config.max_p_value = parse_value::<f64>(option, value)?,
+                "--top-k" => match parse_value::<usize>(option, value)? {
+                    0 => return Err(invalid_value(option, value)),
+                    k => config.top_k = Some(k),
+                },
+                "--top-k-per-target" => match parse_value::<usize>(option, value)? {
+                    0 => return Err(invalid_value(option, value)),
+                    k => config.top_k_per_target = Some(k),
+                },
+                "--top-by" => {
+                    config.top_by = match value.as_str() {
+                        "mi" => TopScore::MutualInformation,
+                        "p-value" => TopScore::PValue,
+                        "information" => TopScore::Information,
+                        _ => return Err(invalid_value(option, value)),
+                    }
+                }
+                "--kernel" => {
+                    config.kernel = match value.as_str() {
+                        "auto" => CountingKernel::Auto,
+                        "scalar" => CountingKernel::Scalar,
+                        "rows" => CountingKernel::Rows,
+                        _ => return Err(invalid_value(option, value)),
+                    }
+                }
+                "--input-format" => config.input_format = parse_input_format(option, value)?,
+                "--validate-pow" => {
+                    config.validate_pow = match value.as_str() {
+                        "report" => Some(PowValidation::Report),
+                        "drop" => Some(PowValidation::Drop),
+                        _ => return Err(invalid_value(option, value)),
+                    }
+                }
+                "--checkpoint" => config.checkpoint_filename = value.to_string(),
+                "--resume" => config.resume_filename = Some(value.to_string()),
+                "--progress-file" => config.progress_filename = Some(value.to_string()),
+                "--progress-interval" => {
+                    config.progress_interval = parse_value::<u64>(option, value)?
+                }
+                "--stream" => match parse_value::<usize>(option, value)? {
+                    0 => return Err(invalid_value(option, value)),
+                    rows => config.chunk_rows = Some(rows),
+                },
+                "--threads" => match parse_value::<usize>(option, value)? {
+                    0 => return Err(invalid_value(option, value)),
+                    threads => config.threads = threads,
+                },
+                "--target" => {
+                    config.target = match parse_target(value) {
+                        Ok(target) => target,
+                        Err(e) => return Err(invalid_value(option, e)),
+                    }
+                }
+                _ => {
+                    show_usage();
+                    return Err(Error::Usage(format!("Unknown option {}", option)));
+                }
+            }
+        }
+        // Derived feature bits are only there with their feature, tuples of
+        // them alone would be empty
+        if let Some(spec) = include_spec {
+            if let Some(feature) = mask_features(spec)
+                .into_iter()
+                .find(|f| !config.features.iter().any(|c| c == f))
+            {
+                return Err(Error::Usage(format!(
+                    "--include {} needs --features {}, its bits are derived from the header",
+                    feature, feature
+                )));
+            }
+        }
+        // Streamed rows are prepared again on every pass over the file, only
+        // the cheap preparations are worth it
+        if config.chunk_rows.is_some() {
+            let costly = [
+                ("--validate-pow", config.validate_pow.is_some()),
+                ("--features", !config.features.is_empty()),
+                ("--target hash", matches!(config.target, Target::Hash)),
+            ];
+            if let Some((option, _)) = costly.iter().find(|(_, given)| *given) {
+                return Err(Error::Usage(format!(
+                    "{} cannot be combined with --stream, it would be computed again by every \
+                     batch: load the hashes file instead",
+                    option
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn parse_simulate_config(args: &[String]) -> Result<(String, MiningParameters), Error> {
+        if args.len() < 3 {
+            show_simulate_usage();
+            return Err(missing_arguments(2, args.len() - 1));
+        }
+
+        let output_prefix = args[1].to_string();
+        let mut params = MiningParameters {
+            blocks: parse_value::<usize>("<blocks>", &args[2])?,
+            version: 0x20000000,
+            bits: 0x2000ffff,
+            // Genesis block time
+            start_time: 1231006505,
+            strategy: NonceStrategy::Sequential,
+            seed: 0,
+        };
+
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            let value = match options.next() {
+                Some(value) => value,
+                None => {
+                    show_simulate_usage();
+                    return Err(Error::Usage(format!("Missing value for option {}", option)));
+                }
+            };
+            match option.as_str() {
+                "--bits" => params.bits = parse_compact_target(option, value)?,
+                "--version" => params.version = parse_hex_u32(option, value)?,
+                "--start-time" => params.start_time = parse_value::<u32>(option, value)?,
+                "--seed" => params.seed = parse_value::<u64>(option, value)?,
+                "--strategy" => match parse_strategy(value) {
+                    Ok(strategy) => params.strategy = strategy,
+                    Err(e) => return Err(invalid_value(option, e)),
+                },
+                _ => {
+                    show_simulate_usage();
+                    return Err(Error::Usage(format!("Unknown option {}", option)));
+                }
+            }
+        }
+        Ok((output_prefix, params))
+    }
+
+    /// A compact target that can be mined in reasonable time
+    fn parse_compact_target(option: &str, value: &str) -> Result<u32, Error> {
+        let bits = parse_hex_u32(option, value)?;
+        match expected_hashes(bits) {
+            Ok(hashes) if hashes > MAX_EXPECTED_HASHES => Err(invalid_value(
+                option,
+                format!(
+                    "target {} needs about {:.0} hashes per block, at most {:.0} are allowed",
+                    value, hashes, MAX_EXPECTED_HASHES
+                ),
+            )),
+            Ok(_) => Ok(bits),
+            Err(e) => Err(invalid_value(option, e)),
+        }
+    }
+
+    fn parse_hex_u32(option: &str, value: &str) -> Result<u32, Error> {
+        u32::from_str_radix(value.trim_start_matches("0x"), 16)
+            .map_err(|_| invalid_value(option, value))
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct ConvertConfig {
+        pub input: String,
+        pub output: String,
+        pub input_format: InputFormat,
+    }
+
+    pub fn parse_convert_config(args: &[String]) -> Result<ConvertConfig, Error> {
+        if args.len() < 3 {
+            show_convert_usage();
+            return Err(missing_arguments(2, args.len() - 1));
+        }
+
+        let mut config = ConvertConfig {
+            input: args[1].to_string(),
+            output: args[2].to_string(),
+            input_format: InputFormat::Bin,
+        };
+
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            let value = match options.next() {
+                Some(value) => value,
+                None => {
+                    show_convert_usage();
+                    return Err(Error::Usage(format!("Missing value for option {}", option)));
+                }
+            };
+            match option.as_str() {
+                "--input-format" => config.input_format = parse_input_format(option, value)?,
+                _ => {
+                    show_convert_usage();
+                    return Err(Error::Usage(format!("Unknown option {}", option)));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn parse_generate_config(args: &[String]) -> Result<GenerateConfig, Error> {
+        if args.len() < 3 {
+            show_generate_usage();
+            return Err(missing_arguments(2, args.len() - 1));
+        }
+
+        let mut config = GenerateConfig {
+            output_prefix: args[1].to_string(),
+            rows: parse_value::<usize>("<rows>", &args[2])?,
+            header_bits: HEADER_BITS,
+            nonce_bits: 32,
+            seed: 0,
+            planted: vec![],
+        };
+
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            let value = match options.next() {
+                Some(value) => value,
+                None => {
+                    show_generate_usage();
+                    return Err(Error::Usage(format!("Missing value for option {}", option)));
+                }
+            };
+            match option.as_str() {
+                "--header-bits" => config.header_bits = parse_value::<u16>(option, value)?,
+                "--nonce-bits" => config.nonce_bits = parse_value::<u16>(option, value)?,
+                "--seed" => config.seed = parse_value::<u64>(option, value)?,
+                "--plant" => match parse_planted(value) {
+                    Ok(planted) => config.planted.push(planted),
+                    Err(e) => return Err(invalid_value(option, e)),
+                },
+                _ => {
+                    show_generate_usage();
+                    return Err(Error::Usage(format!("Unknown option {}", option)));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, Error> {
+        value.parse::<T>().map_err(|_| invalid_value(name, value))
+    }
+
+    fn invalid_value(name: &str, problem: impl Display) -> Error {
+        Error::Usage(format!("Invalid value for {}: {}", name, problem))
+    }
+
+    fn missing_arguments(expected: usize, given: usize) -> Error {
+        Error::Usage(format!("Expected {} arguments, {} given", expected, given))
+    }
+
+    fn parse_input_format(option: &str, value: &str) -> Result<InputFormat, Error> {
+        match value {
+            "bin" => Ok(InputFormat::Bin),
+            "hex" => Ok(InputFormat::Hex),
+            _ => Err(invalid_value(option, value)),
+        }
+    }
+
+    fn parse_mask_option(option: &str, value: &str) -> Result<Vec<u16>, Error> {
+        parse_bit_mask(value).map_err(|e| invalid_value(option, e))
+    }
+
+    /// The derived features a mask names, by themselves or by their bytes
+    fn mask_features(spec: &str) -> Vec<&'static str> {
+        let mut features = vec![];
+        for item in spec.split(',') {
+            let name = item.split('[').next().unwrap_or("").trim();
+            if let Some(feature) = FEATURES.iter().find(|f| **f == name) {
+                if !features.contains(feature) {
+                    features.push(*feature);
+                }
+            }
+        }
+        features
+    }
+
+    fn parse_features(option: &str, value: &str) -> Result<Vec<String>, Error> {
+        let mut features = vec![];
+        for feature in value.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            if !FEATURES.contains(&feature) {
+                return Err(invalid_value(
+                    option,
+                    format!(
+                        "unknown feature {}, available features: {}",
+                        feature,
+                        FEATURES.join(", ")
+                    ),
+                ));
+            }
+            features.push(feature.to_string());
+        }
+        Ok(features)
+    }
+
+    fn show_convert_usage() {
+        println!("\nArgs: convert <hashes file> <packed file> [options]");
+        println!("\nWrites the rows of <hashes file> to <packed file>, bit-packed by column with a description \
+                   of the rows, bit widths, header fields and the SHA-256 of <hashes file>. analyze maps a packed \
+                   file given as hashes file instead of parsing it, and counts its columns as they are mapped unless \
+                   the rows are validated, get derived features or a target other than nonce or header bits.\n");
+        println!("Options:\n\
+                   --input-format <bin|hex>: encoding of header and nonce in the hashes file (default bin).\n");
+    }
+
+    fn show_generate_usage() {
+        println!("\nArgs: generate <output prefix> <rows> [options]");
+        println!("\nWrites <rows> random headers and nonces to <output prefix>_bin.dat and <output prefix>.dat (hex), \
+                   the nonce probabilities to <output prefix>_nonce_probabilities.dat and the planted dependencies to \
+                   <output prefix>_planted.json (usable with --tuples).\n");
+        println!("Options:\n\
+                   --header-bits <n>: header bits per row (default 608).\n\
+                   --nonce-bits <n>: nonce bits per row (default 32).\n\
+                   --seed <n>: seed of the random generator (default 0).\n\
+                   --plant <nonce bit>=<header bit>^<header bit>...@<probability>: make the nonce bit equal to the XOR of the header bits with the given probability (1 if omitted) and its complement otherwise. Can be repeated.\n");
+    }
+
+    fn show_simulate_usage() {
+        println!("\nArgs: simulate <output prefix> <blocks> [options]");
+        println!("\nMines <blocks> blocks on a chain of its own and writes the winning headers and nonces to \
+                   <output prefix>_bin.dat and <output prefix>.dat (hex), and the nonce probabilities to \
+                   <output prefix>_nonce_probabilities.dat.\n");
+        println!("Options:\n\
+                   --bits <hex>: compact difficulty target of the blocks (default 2000ffff, about 1 in 256 hashes, at most 1 in 2^26).\n\
+                   --version <hex>: block version (default 20000000).\n\
+                   --start-time <n>: time of the block before the first one (default the genesis block time).\n\
+                   --strategy <sequential|random|threads:n>: how the nonces are searched: from 0 up (default), from a random nonce up, or with n threads each one walking its own slice of the nonce space from its start.\n\
+                   --seed <n>: seed of the random generator (default 0).\n");
     }
 
     fn show_usage() {
-        println!("\nArgs: <tuple_size> <slice_size> <hashes file> <nonce_probs file> \
-         <start bit> <end bit> <sample threshold> <information threshold>");
-         println!("\n<tuple_size>: Number of bits in the block used to correlate with each nonce bit\n\
+        println!("\nCommands: analyze (default), generate, simulate, convert. Run a command without arguments for its usage.");
+        println!("Exit codes: 2 invalid arguments, 3 unreadable or invalid input file, 4 data that cannot be analyzed as asked, 5 output that cannot be written, 6 failed worker, 130 stopped by a signal.");
+        println!(
+            "\nArgs: [analyze] <tuple_size> <slice_size> <hashes file> <nonce_probs file> \
+         <start bit> <end bit> <sample threshold> <information threshold> [options]"
+        );
+        println!("\n<tuple_size>: Number of bits in the block used to correlate with each nonce bit\n\
                    <slice_size>: Batch of tuples to give to each worker\n\
-                   <hashes file>: file containing the block and hashes\n\
+                   <hashes file>: file containing the block and hashes, as text or packed by convert\n\
                    <nonce_probs file>: file containing the probability of each nonce bit being 1 or 0\n\
                    <start bit>: Starting bit for the first bit in the pair. The program pairs that bit with the remaining ones to create the tuples. E.g. starting in bit 10 means that the first 2-bit tuple to correlate will be [10,11], then [10,12], etc.\n\
                    <end bit>: Last initial bit for the tuple creation. E.g. if the last bit is 12, the last 2-bit tuple will be [12,429]\n\
                    <sample threshold>: minimum number of samples from which the entropy/information pair is calculated: too little samples provide no significance to the entropy/information.\n\
                    <information threshold>: minimum level of information to end up in the file.\n");
+        println!("Options:\n\
+                   --min-bit-entropy <entropy>: header bits whose marginal entropy across the dataset is at or below this value are left out of the tuples (default 0.0, i.e. constant bits are skipped).\n\
+                   --include <mask>: only build tuples from these header bits. The mask is a comma separated list of bit indexes (336), inclusive ranges (544-575) and field names (version, prev_hash, merkle_root, time, bits), bytes of a field (time[0], merkle_root[0-3], counted in serialization order) and single bits of a field (time[0].0, bit 0 being the least significant of the byte).\n\
+                   --exclude <mask>: never use these header bits in the tuples. Same format as --include.\n\
+                   --tuples <file>: score only the tuples listed in the file instead of enumerating them. One JSON address per line, either {{\"header\":[..],\"target_bits\":[..]}} or a full line of a previous output file.\n\
+                   --features <list>: comma separated derived features to add to the header bits: midstate (SHA-256 state after the first 64 bytes of the header, bits 608-863) and schedule (W16 and W17 of the second chunk, bits 864-927). They can be used in masks by name, in --include only when they are given here.\n\
+                   --target <target>: bits to correlate the header tuples with (default nonce). One of nonce, hash (block hash, most significant bit first), time or time:<n> (lowest n bits of the timestamp, default 8), version (BIP320 version rolling bits 13-28), header:<mask> (any header bits, same format as --include) or file:<path> (one string of 0 and 1 per input row, all of the same length, the rows dropped by --validate-pow drop included). The nonce_probs file is only used for the nonce, the entropy of other targets and of joint target bits is measured on the data.\n\
+                   --target-bits <groups>: groups of target bits to correlate jointly with each tuple, separated by ';'. Each group is a comma separated list of target bit indexes and ranges, the first bit being the most significant, e.g. 24-31 is the most significant byte of the nonce (the nonce is stored little-endian) and 0,1;2,3 are two pairs. Up to 8 bits per group.\n\
+                   --target-group-size <n>: when no --target-bits are given, correlate with every combination of n target bits (default 1, each target bit on its own).\n\
+                   --mode <table|parity>: table (default) correlates every value of the header tuple with every value of the target bits. parity correlates the XOR of the header tuple with the XOR of each target group and reports the linear bias P(agree) - 1/2 and its p-value; the information threshold does not apply, statistics are kept by --max-p-value.\n\
+                   --max-p-value <p>: in parity mode, keep the statistics whose bias has a p-value at or below this (default 0.001).\n\
+                   --top-k <k>: keep the k best statistics of the run by --top-by instead of the ones passing the information threshold (or --max-p-value in parity mode), and write them, best first, to experiment04_top_<time>.json at the end of the run, also when stopped by a signal. Instances still need more samples than the sample threshold. Per batch files are not written. A resumed run only ranks its own tuples.\n\
+                   --top-k-per-target <k>: also, or only, keep the k best statistics of each group of target bits, written to experiment04_top_per_target_<time>.json.\n\
+                   --top-by <mi|p-value|information>: what the best statistics have: the highest mutual information (default), the lowest p-value of independence (G-test in table mode, the linear bias in parity mode) or the highest information of an instance. Each statistic gets its score in the output.\n\
+                   --kernel <auto|scalar|rows>: how the rows are counted. auto (default) packs the rows into bit columns and counts them with the vector or popcount instructions of the CPU (an AVX2 nibble lookup or POPCNT, detected at run time), scalar does the same without CPU specific instructions, rows goes through the rows one by one. All give the same results, compare their speed with --dry-run.\n\
+                   --input-format <bin|hex>: encoding of header and nonce in the hashes file, one character per bit (default) or hexadecimal.\n\
+                   --validate-pow <report|drop>: rebuild the 80 byte header of every row and check its double SHA-256 meets the target of its bits field. report lists the failing lines, drop also leaves them out of the analysis. Needs 608 header and 32 nonce bits per row.\n\
+                   --checkpoint <file>: where to write the checkpoint when stopped by SIGINT or SIGTERM (default checkpoint.json). The batches already handed to the workers are finished and saved first, a second signal stops right away.\n\
+                   --resume <file>: skip the tuples scored before the checkpoint, the other arguments must be the ones of the interrupted run.\n\
+                   --progress-interval <secs>: seconds between progress reports (default 10).\n\
+                   --progress-file <file>: also write each progress report to the file, as a line of JSON with elapsed_secs, done, total, percent, addresses_per_sec, eta_secs and the utilisation of each worker.\n\
+                   --stream <rows>: do not load the hashes file, read it <rows> rows at a time whenever the data is gone through, so that memory stays bounded whatever the size of the dataset. Every batch reads the whole file again, use large batches. Not with --validate-pow, --features or --target hash, which would be computed again every time.\n\
+                   --threads <n>: worker threads scoring the batches (default: the cores of the machine).\n\
+                   --dry-run: print the number of addresses and batches, the memory per batch and an estimated runtime from scoring a sample of the addresses for a second, without running the analysis.\n");
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
+        fn analyze_args(options: &[&str]) -> Vec<String> {
+            let args = [
+                "analyze",
+                "2",
+                "1000",
+                "hashes.dat",
+                "probs.dat",
+                "0",
+                "600",
+                "0",
+                "0",
+            ];
+            args.iter().chain(options).map(|a| a.to_string()).collect()
+        }
+
+        #[test]
+        fn derived_masks_need_their_feature() {
+            for options in [
+                vec!["--include", "midstate"],
+                vec!["--include", "0-31,schedule[0]"],
+                vec!["--include", "midstate[0].7", "--features", "schedule"],
+            ] {
+                match parse_config(&analyze_args(&options)) {
+                    Err(e @ Error::Usage(_)) => assert_eq!(e.exit_code(), 2),
+                    other => panic!("{:?}: {:?}", options, other),
+                }
+            }
+            for options in [
+                vec!["--include", "midstate", "--features", "midstate"],
+                vec![
+                    "--features",
+                    "midstate,schedule",
+                    "--include",
+                    "schedule,time",
+                ],
+                // Bits by index can be those of a wider dataset
+                vec!["--include", "600-700"],
+                vec!["--exclude", "midstate"],
+            ] {
+                assert!(
+                    parse_config(&analyze_args(&options)).is_ok(),
+                    "{:?}",
+                    options
+                );
+            }
+        }
     }
 }
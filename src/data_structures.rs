@@ -1,25 +1,118 @@
 pub mod data_structures {
+    use crate::header::layout::{locate_bit, BitLocation};
+    use crate::statistics::ranking::TopResults;
     use json::JsonValue;
     use std::collections::HashMap;
-    use std::sync::mpsc::Sender;
+    use std::time::Duration;
 
     #[derive(Debug, Clone)]
     pub struct BlockHeaderData {
         pub nonce: Vec<bool>,
         pub header: Vec<bool>,
+        // Bits the header tuples are correlated with, see `features::target`
+        pub target: Vec<bool>,
+    }
+
+    /// Text encodings of the header and nonce in the input files
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum InputFormat {
+        /// One character per bit, e.g. `test_bin.dat`
+        Bin,
+        /// One character per 4 bits, e.g. `test.dat`
+        Hex,
+    }
+
+    /// What to do with input rows that fail proof of work validation
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum PowValidation {
+        Report,
+        Drop,
     }
 
     #[derive(Debug, Clone)]
     pub struct DataAddress {
         pub header_bits: Vec<u16>,
-        pub nonce_bit: u8,
+        // Target bits correlated jointly, the first one is the most significant
+        pub target_bits: Vec<u16>,
+    }
+
+    /// What the output records of a run say about their bits besides their
+    /// indexes
+    #[derive(Debug, Clone, Copy)]
+    pub struct RecordFormat {
+        // Header bits laid out like a block header, see `locate_bit`: none
+        // unless the rows have 608 header bits, the derived ones only when
+        // the features are added
+        pub located_bits: u16,
+        // The target is the nonce: a single target bit is also written as
+        // `nonce_bit`, as before joint targets
+        pub nonce_target: bool,
+    }
+
+    impl RecordFormat {
+        /// Where a header bit lies in the block header, if the rows are laid
+        /// out like one
+        pub fn locate_bit(&self, bit: u16) -> Option<BitLocation> {
+            if bit < self.located_bits {
+                locate_bit(bit)
+            } else {
+                None
+            }
+        }
+
+        /// Human readable name of a header bit, e.g. `336 (merkle_root[6].7)`
+        pub fn describe_bit(&self, bit: u16) -> String {
+            match self.locate_bit(bit) {
+                Some(location) => format!("{} ({})", bit, location),
+                None => bit.to_string(),
+            }
+        }
     }
 
-    impl From<DataAddress> for JsonValue {
-        fn from(d: DataAddress) -> Self {
+    impl DataAddress {
+        pub fn into_json(self, format: &RecordFormat) -> JsonValue {
+            let fields: Vec<JsonValue> = self
+                .header_bits
+                .iter()
+                .map(|b| match format.locate_bit(*b) {
+                    Some(location) => location.into(),
+                    None => JsonValue::Null,
+                })
+                .collect();
+            let mut val = json::object! {
+                header: self.header_bits,
+            };
+            if format.located_bits > 0 {
+                val["header_fields"] = fields.into();
+            }
+            if format.nonce_target && self.target_bits.len() == 1 {
+                val["nonce_bit"] = self.target_bits[0].into();
+            }
+            val["target_bits"] = self.target_bits.into();
+            val
+        }
+    }
+
+    /// Where an interrupted analysis stopped. Every tuple up to `last_tuple`, in
+    /// enumeration order, is scored, or the first `tuples_done` tuples when they
+    /// come from a list. The other fields tell which run it belongs to.
+    #[derive(Debug, Clone)]
+    pub struct Checkpoint {
+        pub hashes_file: String,
+        pub tuple_size: u16,
+        pub tuples_file: Option<String>,
+        pub tuples_done: u64,
+        pub last_tuple: Vec<u16>,
+    }
+
+    impl From<Checkpoint> for JsonValue {
+        fn from(c: Checkpoint) -> Self {
             json::object! {
-                header: d.header_bits,
-                nonce_bit: d.nonce_bit,
+                hashes_file: c.hashes_file,
+                tuple_size: c.tuple_size,
+                tuples_file: c.tuples_file,
+                tuples_done: c.tuples_done,
+                last_tuple: c.last_tuple,
             }
         }
     }
@@ -27,54 +120,181 @@ pub mod data_structures {
     #[derive(Debug, Clone)]
     pub struct Statistic {
         pub address: DataAddress,
-        pub instances: HashMap<u32, DataInstance>,
+        // Target value counts of each header tuple value, `target_values` of them
+        // per header value, filled in by the worker
+        pub counts: Vec<u32>,
+        pub target_values: usize,
+        // Derived from the counts once scored, only the instances worth reporting
+        pub instances: Vec<(u32, DataInstance)>,
+        // Between the header tuple and the target, over all the instances
+        pub mutual_information: f32,
+        // Only in parity mode
+        pub linear_bias: Option<LinearBias>,
+        // Only in top-K mode, see `TopScore`
+        pub score: Option<f64>,
     }
 
-    impl From<Statistic> for JsonValue {
-        fn from(s: Statistic) -> Self {
+    impl Statistic {
+        /// The output record of the statistic
+        pub fn into_json(self, format: &RecordFormat) -> JsonValue {
             let mut data = json::JsonValue::new_array();
-            for (k, data_instance) in s.instances {
+            for (k, data_instance) in self.instances {
                 data.push(json::object! { key: k, instance: data_instance})
                     .expect("Error inserting JSON");
             }
 
-            let val = json::object! {
-                address: s.address,
+            let mut val = json::object! {
+                address: self.address.into_json(format),
+                mutual_information: self.mutual_information,
                 instances: data,
             };
+            if let Some(bias) = self.linear_bias {
+                val["linear_bias"] = bias.into();
+            }
+            if let Some(score) = self.score {
+                val["score"] = score.into();
+            }
             val
         }
     }
 
+    /// Target distribution for one value of the header tuple. `counts` and
+    /// `probabilities` have one entry per value of the target bits.
     #[derive(Debug, Clone)]
     pub struct DataInstance {
-        pub zeros: u32,
-        pub ones: u32,
+        pub counts: Vec<u32>,
         pub total: u32,
-        pub p_zero: f32,
-        pub p_one: f32,
+        pub probabilities: Vec<f32>,
         pub entropy: f32,
         pub information: f32,
     }
 
     impl From<DataInstance> for JsonValue {
         fn from(i: DataInstance) -> Self {
+            // Single target bits keep the zeros/ones layout
+            if i.counts.len() == 2 {
+                json::object! {
+                    zeros: i.counts[0],
+                    ones: i.counts[1],
+                    total: i.total,
+                    p_zero: i.probabilities[0],
+                    p_one: i.probabilities[1],
+                    entropy: i.entropy,
+                    information: i.information,
+                }
+            } else {
+                json::object! {
+                    counts: i.counts,
+                    total: i.total,
+                    probabilities: i.probabilities,
+                    entropy: i.entropy,
+                    information: i.information,
+                }
+            }
+        }
+    }
+
+    /// How far the parity of the header tuple agrees with the parity of the
+    /// target bits: `bias` is P(agree) - 1/2, and `p_value` the two sided
+    /// probability of a bias at least this large with independent bits.
+    #[derive(Debug, Clone)]
+    pub struct LinearBias {
+        pub bias: f32,
+        pub z_score: f32,
+        pub p_value: f64,
+    }
+
+    impl From<LinearBias> for JsonValue {
+        fn from(b: LinearBias) -> Self {
             json::object! {
-                zeros: i.zeros,
-                ones: i.ones,
-                total: i.total,
-                p_zero: i.p_zero,
-                p_one: i.p_one,
-                entropy: i.entropy,
-                information: i.information,
+                bias: b.bias,
+                z_score: b.z_score,
+                p_value: b.p_value,
             }
         }
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ScoringMode {
+        /// Full table of target values for every value of the header tuple
+        Table,
+        /// XOR parity of the header tuple against XOR parity of the target bits
+        Parity,
+    }
+
+    /// How the counts of a batch are taken
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CountingKernel {
+        /// Bit-packed columns, popcounted with the best instructions of the CPU
+        Auto,
+        /// Bit-packed columns, popcounted without CPU specific instructions
+        Scalar,
+        /// Row by row, the header and target values of every row
+        Rows,
+    }
+
+    /// What the top-K mode ranks the statistics by
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TopScore {
+        /// Highest mutual information between the header tuple and the target
+        MutualInformation,
+        /// Lowest p-value of the header tuple and the target being independent
+        PValue,
+        /// Highest information of a single instance
+        Information,
+    }
+
+    impl TopScore {
+        pub fn name(&self) -> &'static str {
+            match self {
+                TopScore::MutualInformation => "mutual information",
+                TopScore::PValue => "p-value",
+                TopScore::Information => "instance information",
+            }
+        }
+    }
+
+    /// Keep the best statistics of the run instead of the ones that pass the
+    /// information threshold or the maximum p-value
+    #[derive(Debug, Clone, Copy)]
+    pub struct TopK {
+        pub by: TopScore,
+        // Best statistics over all of them
+        pub global: Option<usize>,
+        // Best statistics of each group of target bits
+        pub per_target: Option<usize>,
+    }
+
+    /// Everything the workers need to score a statistic besides the data
+    #[derive(Debug, Clone)]
+    pub struct Scoring {
+        pub mode: ScoringMode,
+        pub kernel: CountingKernel,
+        // Entropy of each group of target bits (of its parity in parity mode)
+        pub target_entropies: HashMap<Vec<u16>, f32>,
+        pub sample_threshold: usize,
+        pub info_threshold: f32,
+        pub max_p_value: f64,
+        pub top: Option<TopK>,
+    }
+
+    /// What a worker did with a batch
+    #[derive(Debug, Clone)]
+    pub struct BatchReport {
+        pub worker: usize,
+        pub scored: usize,
+        pub busy: Duration,
+    }
+
+    /// A batch scored by a worker, on its way to the output sink
     #[derive(Debug)]
-    pub enum Message {
-        Process(Vec<Statistic>),
-        Stop,
-        Free(Sender<Message>),
+    pub struct BatchResult {
+        pub report: BatchReport,
+        // Header tuples of the batch
+        pub tuples: u64,
+        // The statistics that passed the thresholds
+        pub stats: Vec<Statistic>,
+        // The best statistics of the batch instead, in top-K mode
+        pub top: Option<TopResults>,
     }
 }
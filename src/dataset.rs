@@ -0,0 +1,423 @@
+pub mod dataset {
+    //! The rows to analyze, either all in memory or streamed from the input
+    //! file a chunk at a time, for datasets larger than the memory. Either way
+    //! the rows go through the same preparation before they are counted.
+    use crate::data_structures::data_structures::{BlockHeaderData, InputFormat, PowValidation};
+    use crate::errors::errors::Error;
+    use crate::features::derived::add_derived_features;
+    use crate::features::pow::invalid_proof_of_work;
+    use crate::features::target::{build_targets, Target, TargetFile};
+    use crate::file::file_import::{import_headers_file, HeaderReader};
+    use crate::file::file_packed::{is_packed_file, PackedDataset};
+    use std::ops::Range;
+
+    // Rows of a packed file built at a time for the passes that need rows
+    const PACKED_CHUNK_ROWS: u64 = 1 << 16;
+
+    /// What is done to the rows read from the input before they are analyzed
+    #[derive(Debug, Clone)]
+    pub struct Preparation {
+        pub validation: Option<PowValidation>,
+        // Append the derived features to the header bits
+        pub features: bool,
+        pub target: Target,
+    }
+
+    impl Preparation {
+        /// Validates the proof of work of the rows, dropping the invalid ones if
+        /// asked, and adds the derived features and the targets. Returns the
+        /// input indexes of the invalid rows, the first row having index
+        /// `first_row`.
+        fn apply(
+            &self,
+            rows: &mut Vec<BlockHeaderData>,
+            first_row: u64,
+            target_file: &mut Option<TargetFile>,
+        ) -> Result<Vec<u64>, Error> {
+            let mut invalid = vec![];
+            if self.validation.is_some() {
+                invalid = invalid_proof_of_work(rows).map_err(Error::Data)?;
+            }
+            // A line of the target file per input row, dropped or not
+            if let Some(file) = target_file {
+                file.read(rows)?;
+            }
+            if self.validation == Some(PowValidation::Drop) && !invalid.is_empty() {
+                let mut row = 0;
+                rows.retain(|_| {
+                    row += 1;
+                    invalid.binary_search(&(row - 1)).is_err()
+                });
+            }
+            if self.features {
+                add_derived_features(rows).map_err(Error::Data)?;
+            }
+            if target_file.is_none() {
+                build_targets(rows, &self.target).map_err(Error::Data)?;
+            }
+            Ok(invalid.iter().map(|n| first_row + *n as u64).collect())
+        }
+
+        /// The column of each target bit in a packed file of the given widths,
+        /// when the rows need no preparation beyond their target: no proof of
+        /// work validation, no derived features and the nonce or header bits
+        /// as the target
+        fn target_columns(&self, header_bits: usize, nonce_bits: usize) -> Option<Vec<usize>> {
+            if self.validation.is_some() || self.features {
+                return None;
+            }
+            match &self.target {
+                Target::Nonce => Some((header_bits..header_bits + nonce_bits).collect()),
+                Target::Header(bits) if bits.iter().all(|b| (*b as usize) < header_bits) => {
+                    Some(bits.iter().map(|b| *b as usize).collect())
+                }
+                _ => None,
+            }
+        }
+
+        /// The target file read along with the input, if the target is one
+        fn target_file(&self) -> Result<Option<TargetFile>, Error> {
+            match &self.target {
+                Target::File(path) => TargetFile::open(path).map(Some).map_err(Error::Data),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    /// An input file read `chunk_rows` rows at a time
+    #[derive(Debug, Clone)]
+    pub struct Stream {
+        pub filename: String,
+        pub format: InputFormat,
+        pub chunk_rows: usize,
+        pub preparation: Preparation,
+    }
+
+    impl Stream {
+        pub fn open(&self) -> Result<RowStream<'_>, Error> {
+            Ok(RowStream {
+                stream: self,
+                reader: HeaderReader::open(self.filename.clone(), self.format)?,
+                target_file: self.preparation.target_file()?,
+                rows_read: 0,
+            })
+        }
+    }
+
+    /// Prepared rows of a stream, with the input indexes of the ones failing
+    /// proof of work validation
+    pub struct Chunk {
+        pub rows: Vec<BlockHeaderData>,
+        pub invalid_rows: Vec<u64>,
+    }
+
+    /// The prepared rows of a stream, from the start of its file
+    pub struct RowStream<'a> {
+        stream: &'a Stream,
+        reader: HeaderReader,
+        target_file: Option<TargetFile>,
+        rows_read: u64,
+    }
+
+    impl RowStream<'_> {
+        /// The next chunk of rows, none at the end of the file
+        pub fn next_chunk(&mut self) -> Result<Option<Chunk>, Error> {
+            let mut rows = self.reader.next_chunk(self.stream.chunk_rows)?;
+            if rows.is_empty() {
+                if let Some(file) = &mut self.target_file {
+                    file.finish()?;
+                }
+                return Ok(None);
+            }
+            let first_row = self.rows_read;
+            self.rows_read += rows.len() as u64;
+            let invalid_rows =
+                self.stream
+                    .preparation
+                    .apply(&mut rows, first_row, &mut self.target_file)?;
+            Ok(Some(Chunk { rows, invalid_rows }))
+        }
+    }
+
+    /// A packed file whose columns are counted as they are mapped
+    #[derive(Debug)]
+    pub struct Packed {
+        pub file: PackedDataset,
+        // Column of each target bit
+        target: Vec<usize>,
+    }
+
+    impl Packed {
+        pub fn rows(&self) -> usize {
+            self.file.header.rows as usize
+        }
+
+        pub fn header_column(&self, bit: u16) -> &[u64] {
+            self.file.column(bit as usize)
+        }
+
+        pub fn target_column(&self, bit: u16) -> &[u64] {
+            self.file.column(self.target[bit as usize])
+        }
+
+        /// The rows of the given range, with their targets
+        fn rows_with_targets(&self, range: Range<u64>) -> Vec<BlockHeaderData> {
+            let mut rows = self.file.rows(range.clone());
+            let columns: Vec<&[u64]> = self.target.iter().map(|c| self.file.column(*c)).collect();
+            for (row, entry) in range.zip(rows.iter_mut()) {
+                let (word, shift) = ((row / 64) as usize, row % 64);
+                entry.target = columns
+                    .iter()
+                    .map(|c| (c[word] >> shift) & 1 == 1)
+                    .collect();
+            }
+            rows
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum Dataset {
+        Memory(Vec<BlockHeaderData>),
+        Stream(Stream),
+        Packed(Packed),
+    }
+
+    impl Dataset {
+        /// Calls `f` with the rows a chunk at a time, or all at once when they
+        /// are in memory. The rows of a packed file are built a chunk at a
+        /// time, for the passes that cannot count its columns.
+        pub fn for_each_chunk(
+            &self,
+            mut f: impl FnMut(&[BlockHeaderData]) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            match self {
+                Dataset::Memory(rows) => f(rows),
+                Dataset::Stream(stream) => {
+                    let mut rows = stream.open()?;
+                    while let Some(chunk) = rows.next_chunk()? {
+                        f(&chunk.rows)?;
+                    }
+                    Ok(())
+                }
+                Dataset::Packed(packed) => {
+                    let rows = packed.file.header.rows;
+                    for start in (0..rows).step_by(PACKED_CHUNK_ROWS as usize) {
+                        let end = (start + PACKED_CHUNK_ROWS).min(rows);
+                        f(&packed.rows_with_targets(start..end))?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// What the analysis needs to know about the rows before going through them
+    #[derive(Debug, Clone, Default)]
+    pub struct Summary {
+        // Rows read from the input
+        pub rows_read: u64,
+        // Rows left once the invalid ones are dropped
+        pub rows: u64,
+        pub header_len: usize,
+        pub nonce_len: usize,
+        pub target_len: usize,
+        // Number of ones of each header bit
+        pub header_ones: Vec<u64>,
+        // Input indexes of the rows failing proof of work validation
+        pub invalid_rows: Vec<u64>,
+    }
+
+    impl Summary {
+        fn add(&mut self, rows: &[BlockHeaderData]) {
+            if let Some(first) = rows.first() {
+                if self.rows == 0 {
+                    self.header_len = first.header.len();
+                    self.nonce_len = first.nonce.len();
+                    self.target_len = first.target.len();
+                    self.header_ones = vec![0; self.header_len];
+                }
+            }
+            for entry in rows {
+                for (ones, value) in self.header_ones.iter_mut().zip(&entry.header) {
+                    *ones += *value as u64;
+                }
+            }
+            self.rows += rows.len() as u64;
+        }
+    }
+
+    /// Reads and prepares the rows of the input, all of them, or only a chunk
+    /// at a time when `chunk_rows` is given, and summarizes them. Streamed rows
+    /// are read again by every pass over the dataset. A packed file whose rows
+    /// need no preparation is only mapped, whatever `chunk_rows`.
+    pub fn open_dataset(
+        filename: &str,
+        format: InputFormat,
+        chunk_rows: Option<usize>,
+        preparation: Preparation,
+    ) -> Result<(Dataset, Summary), Error> {
+        let mut summary = Summary::default();
+        if is_packed_file(filename) {
+            let file = PackedDataset::open(filename)?;
+            let (header_bits, nonce_bits) = (file.header.header_bits, file.header.nonce_bits);
+            if let Some(target) = preparation.target_columns(header_bits, nonce_bits) {
+                println!("{}", file.header.describe(filename));
+                summary.rows_read = file.header.rows;
+                summary.rows = file.header.rows;
+                summary.header_len = header_bits;
+                summary.nonce_len = nonce_bits;
+                summary.target_len = target.len();
+                summary.header_ones = (0..header_bits)
+                    .map(|bit| file.column(bit).iter().map(|w| w.count_ones() as u64).sum())
+                    .collect();
+                return Ok((Dataset::Packed(Packed { file, target }), summary));
+            }
+        }
+        match chunk_rows {
+            None => {
+                let mut rows = import_headers_file(filename.to_string(), format)?;
+                summary.rows_read = rows.len() as u64;
+                let mut target_file = preparation.target_file()?;
+                summary.invalid_rows = preparation.apply(&mut rows, 0, &mut target_file)?;
+                if let Some(file) = &mut target_file {
+                    file.finish()?;
+                }
+                summary.add(&rows);
+                Ok((Dataset::Memory(rows), summary))
+            }
+            Some(chunk_rows) => {
+                let stream = Stream {
+                    filename: filename.to_string(),
+                    format,
+                    chunk_rows,
+                    preparation,
+                };
+                let mut rows = stream.open()?;
+                while let Some(chunk) = rows.next_chunk()? {
+                    summary.invalid_rows.extend(chunk.invalid_rows);
+                    summary.add(&chunk.rows);
+                }
+                summary.rows_read = rows.rows_read;
+                Ok((Dataset::Stream(stream), summary))
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::file::file_export::save_headers_to_file;
+        use crate::generator::mining::{mine_chain, MiningParameters, NonceStrategy};
+
+        /// A mined chain, every third block with a nonce that fails its proof of
+        /// work
+        fn rows() -> Vec<BlockHeaderData> {
+            let mut rows = mine_chain(&MiningParameters {
+                blocks: 30,
+                version: 0x2000_0000,
+                bits: 0x2000_ffff,
+                start_time: 1_600_000_000,
+                strategy: NonceStrategy::Sequential,
+                seed: 11,
+            })
+            .unwrap();
+            for row in rows.iter_mut().step_by(3) {
+                row.nonce[31] = !row.nonce[31];
+            }
+            rows
+        }
+
+        /// The nonce of each row as its target line
+        fn nonce_lines(rows: &[BlockHeaderData]) -> Vec<String> {
+            rows.iter()
+                .map(|row| {
+                    row.nonce
+                        .iter()
+                        .map(|b| if *b { '1' } else { '0' })
+                        .collect()
+                })
+                .collect()
+        }
+
+        /// Writes the rows and a target file of the given lines, and opens them
+        fn open(
+            rows: &[BlockHeaderData],
+            targets: &[String],
+            name: &str,
+            chunk_rows: Option<usize>,
+        ) -> Result<Vec<BlockHeaderData>, Error> {
+            let dir = std::env::temp_dir();
+            let pid = std::process::id();
+            let hashes = dir.join(format!("{}-{}.dat", name, pid));
+            let hashes = hashes.to_str().unwrap();
+            let target = dir.join(format!("{}-{}.target", name, pid));
+            let target = target.to_str().unwrap();
+            save_headers_to_file(hashes, rows, InputFormat::Bin).unwrap();
+            std::fs::write(target, targets.join("\n")).unwrap();
+            let preparation = Preparation {
+                validation: Some(PowValidation::Drop),
+                features: false,
+                target: Target::File(target.to_string()),
+            };
+            // Read through the chunks, streamed rows are prepared as they come
+            let read = open_dataset(hashes, InputFormat::Bin, chunk_rows, preparation).and_then(
+                |(data, _)| {
+                    let mut read = vec![];
+                    data.for_each_chunk(|rows| {
+                        read.extend_from_slice(rows);
+                        Ok(())
+                    })?;
+                    Ok(read)
+                },
+            );
+            std::fs::remove_file(hashes).unwrap();
+            std::fs::remove_file(target).unwrap();
+            read
+        }
+
+        #[test]
+        fn file_targets_stay_with_their_rows_when_rows_are_dropped() {
+            let rows = rows();
+            let invalid = invalid_proof_of_work(&rows).unwrap();
+            assert_eq!(invalid, (0..30).step_by(3).collect::<Vec<_>>());
+            let targets = nonce_lines(&rows);
+            for chunk_rows in [None, Some(7)] {
+                let read = open(&rows, &targets, "bt-drop-target", chunk_rows).unwrap();
+                assert_eq!(read.len(), 20, "{:?}", chunk_rows);
+                for row in &read {
+                    assert_eq!(row.target, row.nonce, "{:?}", chunk_rows);
+                }
+            }
+        }
+
+        #[test]
+        fn target_file_has_a_line_per_row() {
+            let rows = rows();
+            let targets = nonce_lines(&rows);
+            for chunk_rows in [None, Some(7)] {
+                // Blank lines do not count
+                let mut blank = targets.clone();
+                blank.push(String::new());
+                assert!(open(&rows, &blank, "bt-blank-target", chunk_rows).is_ok());
+
+                let mut more = targets.clone();
+                more.push(targets[0].clone());
+                match open(&rows, &more, "bt-more-target", chunk_rows) {
+                    Err(Error::Import(e)) => {
+                        assert_eq!(e.line, 31);
+                        assert_eq!(e.problem, "more lines than the input data");
+                    }
+                    other => panic!("{:?}", other.map(|rows| rows.len())),
+                }
+
+                match open(&rows, &targets[..29], "bt-fewer-target", chunk_rows) {
+                    Err(Error::Import(e)) => {
+                        assert_eq!(e.line, 30);
+                        assert_eq!(e.problem, "fewer lines than the input data");
+                    }
+                    other => panic!("{:?}", other.map(|rows| rows.len())),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+pub mod errors {
+    use crate::file::file_import::ImportError;
+    use std::fmt;
+
+    /// Everything that can stop a command, each kind with its own exit code
+    #[derive(Debug)]
+    pub enum Error {
+        /// Invalid command line arguments
+        Usage(String),
+        /// An input file that cannot be read or has invalid contents
+        Import(ImportError),
+        /// Data that cannot be analyzed as asked, e.g. a tuple out of its range
+        Data(String),
+        /// An output file that cannot be written
+        Export(String, std::io::Error),
+        /// A worker thread that went away without finishing its work
+        Worker(String),
+        /// Stopped by a signal before the end, see `shutdown`
+        Interrupted,
+    }
+
+    impl Error {
+        pub fn exit_code(&self) -> i32 {
+            match self {
+                Error::Usage(_) => 2,
+                Error::Import(_) => 3,
+                Error::Data(_) => 4,
+                Error::Export(_, _) => 5,
+                Error::Worker(_) => 6,
+                Error::Interrupted => 130,
+            }
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::Usage(e) => write!(f, "{}", e),
+                Error::Import(e) => write!(f, "Cannot import {}", e),
+                Error::Data(e) => write!(f, "{}", e),
+                Error::Export(file, e) => write!(f, "Cannot write {}: {}", file, e),
+                Error::Worker(e) => write!(f, "Worker failed: {}", e),
+                Error::Interrupted => write!(f, "Interrupted"),
+            }
+        }
+    }
+
+    impl From<ImportError> for Error {
+        fn from(e: ImportError) -> Self {
+            Error::Import(e)
+        }
+    }
+}
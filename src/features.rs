@@ -0,0 +1,673 @@
+pub mod sha256 {
+    //! Plain SHA-256, with the compression function and message schedule exposed
+    //! so the state between the two chunks of a block header can be inspected.
+
+    pub const INITIAL_STATE: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    pub fn small_sigma0(x: u32) -> u32 {
+        x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3)
+    }
+
+    pub fn small_sigma1(x: u32) -> u32 {
+        x.rotate_right(17) ^ x.rotate_right(19) ^ (x >> 10)
+    }
+
+    /// The 64 words of the expanded message schedule of a chunk
+    pub fn message_schedule(chunk: &[u8; 64]) -> [u32; 64] {
+        let mut w = [0u32; 64];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            w[i] = small_sigma1(w[i - 2])
+                .wrapping_add(w[i - 7])
+                .wrapping_add(small_sigma0(w[i - 15]))
+                .wrapping_add(w[i - 16]);
+        }
+        w
+    }
+
+    /// Applies the compression function of one chunk to the state
+    pub fn compress(state: &mut [u32; 8], chunk: &[u8; 64]) {
+        let w = message_schedule(chunk);
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *s = s.wrapping_add(*v);
+        }
+    }
+
+    /// The message split in padded 64 byte chunks
+    pub fn padded_chunks(data: &[u8]) -> Vec<[u8; 64]> {
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+        message
+            .chunks(64)
+            .map(|c| {
+                let mut chunk = [0u8; 64];
+                chunk.copy_from_slice(c);
+                chunk
+            })
+            .collect()
+    }
+
+    pub fn digest(data: &[u8]) -> [u8; 32] {
+        let mut state = INITIAL_STATE;
+        for chunk in padded_chunks(data) {
+            compress(&mut state, &chunk);
+        }
+        let mut result = [0u8; 32];
+        for (i, word) in state.iter().enumerate() {
+            result[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+        }
+        result
+    }
+
+    /// SHA-256 applied twice, as used for the block hash
+    pub fn double_digest(data: &[u8]) -> [u8; 32] {
+        digest(&digest(data))
+    }
+
+    /// SHA-256 of a message given a piece at a time, e.g. a file too large to
+    /// be read at once
+    pub struct Hasher {
+        state: [u32; 8],
+        // Bytes not making a whole chunk yet
+        pending: Vec<u8>,
+        length: u64,
+    }
+
+    impl Hasher {
+        pub fn new() -> Hasher {
+            Hasher {
+                state: INITIAL_STATE,
+                pending: Vec::with_capacity(64),
+                length: 0,
+            }
+        }
+
+        pub fn update(&mut self, data: &[u8]) {
+            self.length += data.len() as u64;
+            self.pending.extend_from_slice(data);
+            let whole = self.pending.len() / 64 * 64;
+            for c in self.pending[..whole].chunks_exact(64) {
+                let mut chunk = [0u8; 64];
+                chunk.copy_from_slice(c);
+                compress(&mut self.state, &chunk);
+            }
+            self.pending.drain(..whole);
+        }
+
+        pub fn finish(mut self) -> [u8; 32] {
+            // Padded like `padded_chunks` does, with the length of the whole message
+            let mut message = std::mem::take(&mut self.pending);
+            message.push(0x80);
+            while message.len() % 64 != 56 {
+                message.push(0);
+            }
+            message.extend_from_slice(&(self.length * 8).to_be_bytes());
+            for c in message.chunks_exact(64) {
+                let mut chunk = [0u8; 64];
+                chunk.copy_from_slice(c);
+                compress(&mut self.state, &chunk);
+            }
+            let mut result = [0u8; 32];
+            for (i, word) in self.state.iter().enumerate() {
+                result[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+            }
+            result
+        }
+    }
+
+    impl Default for Hasher {
+        fn default() -> Self {
+            Hasher::new()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        fn from_hex(hex: &str) -> Vec<u8> {
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect()
+        }
+
+        // FIPS 180-2 appendix B
+        const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        const TWO_CHUNKS: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        const TWO_CHUNKS_DIGEST: &str =
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1";
+        const MILLION_A: &str = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+
+        #[test]
+        fn digests_match_fips_vectors() {
+            assert_eq!(
+                hex(&digest(b"")),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            );
+            assert_eq!(hex(&digest(b"abc")), ABC);
+            // 56 bytes leave no room for the length, the padding takes a chunk
+            assert_eq!(padded_chunks(TWO_CHUNKS).len(), 2);
+            assert_eq!(hex(&digest(TWO_CHUNKS)), TWO_CHUNKS_DIGEST);
+        }
+
+        #[test]
+        fn hasher_matches_digest_whatever_the_pieces() {
+            let mut hasher = Hasher::new();
+            hasher.update(b"ab");
+            hasher.update(b"");
+            hasher.update(b"c");
+            assert_eq!(hex(&hasher.finish()), ABC);
+
+            let mut hasher = Hasher::new();
+            for piece in TWO_CHUNKS.chunks(13) {
+                hasher.update(piece);
+            }
+            assert_eq!(hex(&hasher.finish()), TWO_CHUNKS_DIGEST);
+
+            // Pieces shorter and longer than a chunk, across chunk boundaries
+            let message = vec![b'a'; 1_000_000];
+            let mut hasher = Hasher::new();
+            let mut rest = &message[..];
+            for size in [1, 63, 64, 65, 127, 1000].iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (piece, next) = rest.split_at((*size).min(rest.len()));
+                hasher.update(piece);
+                rest = next;
+            }
+            assert_eq!(hex(&hasher.finish()), MILLION_A);
+        }
+
+        #[test]
+        fn block_header_midstate_and_hash() {
+            // Block 125552 of Bitcoin
+            let header = from_hex(concat!(
+                "01000000",
+                "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
+                "e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122b",
+                "c7f5d74d",
+                "f2b9441a",
+                "42a14695"
+            ));
+            let chunks = padded_chunks(&header);
+            assert_eq!(chunks.len(), 2);
+            let mut midstate = INITIAL_STATE;
+            compress(&mut midstate, &chunks[0]);
+            assert_eq!(
+                midstate,
+                [
+                    0x9524c593, 0x05c56713, 0x16e669ba, 0x2d2810a0, 0x07e86e37, 0x2f56a9da,
+                    0xcd5bce69, 0x7a78da2d
+                ]
+            );
+
+            // The second chunk from the midstate gives the first digest
+            let mut state = midstate;
+            compress(&mut state, &chunks[1]);
+            let first: Vec<u8> = state.iter().flat_map(|w| w.to_be_bytes()).collect();
+            assert_eq!(first, digest(&header));
+
+            let mut hash = double_digest(&header);
+            hash.reverse();
+            assert_eq!(
+                hex(&hash),
+                "00000000000000001e8d6829a8a21adc5d38d0a473b144b6765798e61f98bd1d"
+            );
+        }
+    }
+}
+
+pub mod pow {
+    //! Proof of work: the target encoded in the header `bits` field and the
+    //! check of a block hash against it.
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::features::sha256::double_digest;
+    use crate::header::layout::{bits_to_bytes, field_u32, HEADER_BITS};
+
+    /// Target of the compact `bits` encoding, as a big-endian 256 bit number.
+    /// Negative targets (sign bit set) are returned as zero.
+    pub fn compact_to_target(bits: u32) -> [u8; 32] {
+        let mut target = [0u8; 32];
+        let exponent = (bits >> 24) as i32;
+        let mantissa = bits & 0x007fffff;
+        if bits & 0x00800000 != 0 {
+            return target;
+        }
+        for i in 0..3 {
+            // Mantissa byte i (most significant first) ends up exponent - i
+            // bytes from the least significant end
+            let position = 32 - exponent + i;
+            if (0..32).contains(&position) {
+                target[position as usize] = (mantissa >> (8 * (2 - i))) as u8;
+            }
+        }
+        target
+    }
+
+    /// Expected number of hashes to find a block at the compact target `bits`,
+    /// 2^256 / (target + 1). Fails on the targets that no hash can meet or
+    /// that do not fit in 256 bits: negative, zero and overflowing ones.
+    pub fn expected_hashes(bits: u32) -> Result<f64, String> {
+        let exponent = bits >> 24;
+        let mantissa = bits & 0x007fffff;
+        if bits & 0x00800000 != 0 {
+            return Err(format!("target {:08x} is negative", bits));
+        }
+        if mantissa != 0
+            && (exponent > 34
+                || (mantissa > 0xff && exponent > 33)
+                || (mantissa > 0xffff && exponent > 32))
+        {
+            return Err(format!("target {:08x} does not fit in 256 bits", bits));
+        }
+        let target = compact_to_target(bits);
+        if target.iter().all(|b| *b == 0) {
+            return Err(format!("target {:08x} is zero", bits));
+        }
+        let value = target.iter().fold(0.0, |v, b| v * 256.0 + *b as f64);
+        Ok(2f64.powi(256) / (value + 1.0))
+    }
+
+    /// Whether a hash, as returned by `double_digest`, is at or below the
+    /// target. Hashes are compared as little-endian numbers.
+    pub fn hash_meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
+        let mut value = *hash;
+        value.reverse();
+        value <= *target
+    }
+
+    /// Rebuilds the 80 byte header of every entry and returns the indexes of the
+    /// ones whose hash does not meet the target of their own `bits` field. A
+    /// failing entry is corrupted or misaligned, or not a block header at all.
+    pub fn invalid_proof_of_work(data: &[BlockHeaderData]) -> Result<Vec<usize>, String> {
+        let mut invalid = vec![];
+        for (n, entry) in data.iter().enumerate() {
+            if entry.header.len() < HEADER_BITS as usize || entry.nonce.len() != 32 {
+                return Err(format!(
+                    "Entry {} has {} header and {} nonce bits, proof of work validation \
+                     needs the full {} bit header and a 32 bit nonce",
+                    n,
+                    entry.header.len(),
+                    entry.nonce.len(),
+                    HEADER_BITS
+                ));
+            }
+            let mut bytes = bits_to_bytes(&entry.header[..HEADER_BITS as usize]);
+            bytes.extend(bits_to_bytes(&entry.nonce));
+            let target = compact_to_target(field_u32(&entry.header, "bits"));
+            if !hash_meets_target(&double_digest(&bytes), &target) {
+                invalid.push(n);
+            }
+        }
+        Ok(invalid)
+    }
+}
+
+pub mod derived {
+    //! Features computed from the raw header bits and appended after them, so
+    //! they can be addressed and combined in tuples like any other header bit.
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::features::sha256::{compress, message_schedule, padded_chunks, INITIAL_STATE};
+    use crate::header::layout::{bits_to_bytes, words_to_bits, HEADER_BITS};
+
+    pub const FEATURES: [&str; 2] = ["midstate", "schedule"];
+
+    /// Appends the derived features to the header of every entry. The first
+    /// SHA-256 chunk is made of the first 64 bytes of the header, so the state
+    /// after it (the midstate) is what the nonce is actually mixed with. Of the
+    /// expanded schedule of the second chunk only W16 and W17 are kept, W0-W2
+    /// are raw header bits already, W3 is the nonce and from W18 on every word
+    /// depends on the nonce.
+    pub fn add_derived_features(data: &mut [BlockHeaderData]) -> Result<(), String> {
+        for (n, entry) in data.iter_mut().enumerate() {
+            if entry.header.len() != HEADER_BITS as usize {
+                return Err(format!(
+                    "Entry {} has {} header bits, derived features need the full {} bit header",
+                    n,
+                    entry.header.len(),
+                    HEADER_BITS
+                ));
+            }
+            let mut bytes = bits_to_bytes(&entry.header);
+            // The nonce is not part of the header bits, and W16 and W17 do not
+            // depend on it, so any value works for building the second chunk
+            bytes.extend_from_slice(&[0u8; 4]);
+            let chunks = padded_chunks(&bytes);
+
+            let mut midstate = INITIAL_STATE;
+            compress(&mut midstate, &chunks[0]);
+            let schedule = message_schedule(&chunks[1]);
+
+            entry.header.extend(words_to_bits(&midstate));
+            entry.header.extend(words_to_bits(&schedule[16..18]));
+        }
+        Ok(())
+    }
+}
+
+pub mod target {
+    //! The bits the header tuples are correlated with. The nonce is what we are
+    //! after, the other targets have known dependencies on the header and are
+    //! useful to check that the analysis finds what it should.
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::data_structures::data_structures::InputFormat;
+    use crate::features::sha256::double_digest;
+    use crate::file::file_import::{decode_field, ImportError};
+    use crate::header::layout::{
+        bits_to_bytes, bytes_to_bits, field_u32, parse_bit_mask, HEADER_BITS,
+    };
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Lines};
+
+    #[derive(Debug, Clone)]
+    pub enum Target {
+        /// The nonce bits as read from the input
+        Nonce,
+        /// Block hash, most significant bit of the hash value first
+        Hash,
+        /// Lowest bits of the timestamp, least significant first
+        Time(u8),
+        /// BIP320 version rolling bits (13 to 28), least significant first
+        VersionRolling,
+        /// Any header bits, including derived features
+        Header(Vec<u16>),
+        /// One bit string per input line, read from a separate file
+        File(String),
+    }
+
+    /// Parses `nonce`, `hash`, `time`, `time:<bits>`, `version`,
+    /// `header:<mask>` or `file:<path>`
+    pub fn parse_target(spec: &str) -> Result<Target, String> {
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (spec, None),
+        };
+        match (name, arg) {
+            ("nonce", None) => Ok(Target::Nonce),
+            ("hash", None) => Ok(Target::Hash),
+            ("time", None) => Ok(Target::Time(8)),
+            ("time", Some(bits)) => match bits.parse::<u8>() {
+                Ok(bits) if bits > 0 && bits <= 32 => Ok(Target::Time(bits)),
+                _ => Err(format!("Invalid number of time bits '{}'", bits)),
+            },
+            ("version", None) => Ok(Target::VersionRolling),
+            ("header", Some(mask)) => Ok(Target::Header(parse_bit_mask(mask)?)),
+            ("file", Some(path)) => Ok(Target::File(path.to_string())),
+            _ => Err(format!("Unknown target '{}'", spec)),
+        }
+    }
+
+    /// Largest number of target bits correlated jointly
+    pub const MAX_GROUP_SIZE: usize = 8;
+
+    /// Parses groups of target bits separated by `;`, each one a comma separated
+    /// list of bit indexes and inclusive ranges, e.g. `24-31` or `0,1;2,3`
+    pub fn parse_target_groups(spec: &str) -> Result<Vec<Vec<u16>>, String> {
+        let mut groups = vec![];
+        for group_spec in spec.split(';').map(|g| g.trim()).filter(|g| !g.is_empty()) {
+            let mut group: Vec<u16> = vec![];
+            for item in group_spec.split(',').map(|i| i.trim()) {
+                let parse = |v: &str| {
+                    v.trim()
+                        .parse::<u16>()
+                        .map_err(|_| format!("Invalid target bit '{}'", v))
+                };
+                match item.split_once('-') {
+                    Some((from, to)) => group.extend(parse(from)?..=parse(to)?),
+                    None => group.push(parse(item)?),
+                }
+            }
+            if group.is_empty() || group.len() > MAX_GROUP_SIZE {
+                return Err(format!(
+                    "Target groups need between 1 and {} bits, '{}' has {}",
+                    MAX_GROUP_SIZE,
+                    group_spec,
+                    group.len()
+                ));
+            }
+            groups.push(group);
+        }
+        Ok(groups)
+    }
+
+    /// The groups of target bits each header tuple is correlated with: the
+    /// given ones, or else every combination of `group_size` target bits
+    pub fn target_groups(
+        target_len: u16,
+        explicit: Option<&[Vec<u16>]>,
+        group_size: u16,
+    ) -> Result<Vec<Vec<u16>>, String> {
+        if let Some(groups) = explicit {
+            if let Some(bit) = groups.iter().flatten().find(|b| **b >= target_len) {
+                return Err(format!(
+                    "Target bit {} is out of the {} target bits",
+                    bit, target_len
+                ));
+            }
+            return Ok(groups.to_vec());
+        }
+        if group_size == 0 || group_size as usize > MAX_GROUP_SIZE {
+            return Err(format!(
+                "Target group size must be between 1 and {}",
+                MAX_GROUP_SIZE
+            ));
+        }
+
+        let mut groups = vec![];
+        let mut group: Vec<u16> = (0..group_size).collect();
+        if group_size > target_len {
+            return Ok(groups);
+        }
+        loop {
+            groups.push(group.clone());
+            // Next combination in lexicographic order
+            let k = group.len();
+            let mut i = k;
+            while i > 0 && group[i - 1] == target_len - (k - i + 1) as u16 {
+                i -= 1;
+            }
+            if i == 0 {
+                return Ok(groups);
+            }
+            group[i - 1] += 1;
+            for j in i..k {
+                group[j] = group[j - 1] + 1;
+            }
+        }
+    }
+
+    /// Fills the target bits of every entry. The targets of a `file:` target
+    /// are read with a `TargetFile` instead.
+    pub fn build_targets(data: &mut [BlockHeaderData], target: &Target) -> Result<(), String> {
+        if let Target::File(path) = target {
+            return Err(format!("The targets of {} are read from the file", path));
+        }
+        for (n, entry) in data.iter_mut().enumerate() {
+            let needs_header = matches!(
+                target,
+                Target::Hash | Target::Time(_) | Target::VersionRolling
+            );
+            if needs_header && entry.header.len() < HEADER_BITS as usize {
+                return Err(format!(
+                    "Entry {} has {} header bits, the target needs the full {} bit header",
+                    n,
+                    entry.header.len(),
+                    HEADER_BITS
+                ));
+            }
+            entry.target = match target {
+                Target::Nonce => entry.nonce.clone(),
+                Target::Hash => {
+                    let mut bytes = bits_to_bytes(&entry.header[..HEADER_BITS as usize]);
+                    bytes.extend(bits_to_bytes(&entry.nonce));
+                    let mut hash = double_digest(&bytes);
+                    hash.reverse();
+                    bytes_to_bits(&hash)
+                }
+                Target::Time(bits) => {
+                    let time = field_u32(&entry.header, "time");
+                    (0..*bits).map(|i| (time >> i) & 1 == 1).collect()
+                }
+                Target::VersionRolling => {
+                    let version = field_u32(&entry.header, "version");
+                    (13..29).map(|i| (version >> i) & 1 == 1).collect()
+                }
+                Target::Header(bits) => {
+                    let mut values = vec![];
+                    for bit in bits {
+                        match entry.header.get(*bit as usize) {
+                            Some(value) => values.push(*value),
+                            None => {
+                                return Err(format!(
+                                    "Target header bit {} is out of the {} header bits",
+                                    bit,
+                                    entry.header.len()
+                                ))
+                            }
+                        }
+                    }
+                    values
+                }
+                Target::File(_) => unreachable!(),
+            };
+        }
+        Ok(())
+    }
+
+    /// The file of a `file:` target, read along with the rows when they come
+    /// in chunks. Its lines are checked like the input headers: only 0 and 1,
+    /// the same number of bits on every line, blank lines skipped. It has one
+    /// line per input row, no fewer and no more.
+    pub struct TargetFile {
+        path: String,
+        lines: Lines<BufReader<File>>,
+        line: u64,
+        width: Option<usize>,
+    }
+
+    impl TargetFile {
+        pub fn open(path: &str) -> Result<TargetFile, String> {
+            let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+            Ok(TargetFile {
+                path: path.to_string(),
+                lines: BufReader::new(file).lines(),
+                line: 0,
+                width: None,
+            })
+        }
+
+        /// Fills the target bits of the entries from the next lines of the file
+        pub fn read(&mut self, data: &mut [BlockHeaderData]) -> Result<(), ImportError> {
+            for entry in data.iter_mut() {
+                entry.target = self.next_target()?;
+            }
+            Ok(())
+        }
+
+        /// Checks that the file has no lines left once the input is read
+        pub fn finish(&mut self) -> Result<(), ImportError> {
+            match self.next_line()? {
+                Some(_) => Err(self.error("more lines than the input data".to_string())),
+                None => Ok(()),
+            }
+        }
+
+        fn next_target(&mut self) -> Result<Vec<bool>, ImportError> {
+            match self.next_line()? {
+                Some(line) => self.decode(&line),
+                None => Err(self.error("fewer lines than the input data".to_string())),
+            }
+        }
+
+        /// The next line that is not blank, none at the end of the file
+        fn next_line(&mut self) -> Result<Option<String>, ImportError> {
+            loop {
+                self.line += 1;
+                let line = match self.lines.next() {
+                    Some(Ok(line)) => line,
+                    Some(Err(e)) => return Err(self.error(e.to_string())),
+                    None => return Ok(None),
+                };
+                if !line.trim().is_empty() {
+                    return Ok(Some(line));
+                }
+            }
+        }
+
+        fn decode(&mut self, line: &str) -> Result<Vec<bool>, ImportError> {
+            let bits = decode_field(line.trim().as_bytes(), InputFormat::Bin)
+                .map_err(|p| self.error(format!("target {}", p)))?;
+            match self.width {
+                Some(width) if bits.len() != width => {
+                    return Err(self.error(format!(
+                        "{} target bits, the first line has {}",
+                        bits.len(),
+                        width
+                    )))
+                }
+                Some(_) => {}
+                None => self.width = Some(bits.len()),
+            }
+            Ok(bits)
+        }
+
+        fn error(&self, problem: String) -> ImportError {
+            ImportError {
+                file: self.path.clone(),
+                line: self.line,
+                problem,
+            }
+        }
+    }
+}
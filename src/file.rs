@@ -1,55 +1,455 @@
 pub mod file_import {
-    use crate::data_structures::data_structures::BlockHeaderData;
-    use regex::Regex;
+    use crate::data_structures::data_structures::{
+        BlockHeaderData, Checkpoint, DataAddress, InputFormat,
+    };
+    use crate::file::file_packed::{is_packed_file, PackedDataset, PackedHeader};
     use std::collections::HashMap;
+    use std::fmt;
     use std::fs::File;
     use std::io::{BufRead, BufReader};
+    use std::time::Instant;
 
-    pub fn import_headers_file(filename: String) -> Vec<BlockHeaderData> {
-        let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+    /// A problem found while loading an input file. `line` counts from 1, 0
+    /// means the problem is with the file as a whole.
+    #[derive(Debug, Clone)]
+    pub struct ImportError {
+        pub file: String,
+        pub line: u64,
+        pub problem: String,
+    }
+
+    impl fmt::Display for ImportError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}:{}: {}", self.file, self.line, self.problem)
+        }
+    }
+
+    /// Reads the header and nonce of every line. All the lines must have the
+    /// same header and nonce widths as the first one, the rest of the program
+    /// relies on it.
+    pub fn import_headers_file(
+        filename: String,
+        format: InputFormat,
+    ) -> Result<Vec<BlockHeaderData>, ImportError> {
+        let start = Instant::now();
+        let mut reader = HeaderReader::open(filename.clone(), format)?;
+        if let Some(packed) = reader.packed() {
+            println!("{}", packed.describe(&filename));
+        }
         let mut data: Vec<BlockHeaderData> = Vec::new();
+        loop {
+            let chunk = reader.next_chunk(100000)?;
+            if chunk.is_empty() {
+                break;
+            }
+            data.extend(chunk);
+            if reader.packed().is_none() {
+                println!("Processing hash file line: {}", reader.line);
+            }
+        }
+        if reader.packed().is_none() {
+            let secs = start.elapsed().as_secs_f64();
+            let mib = reader.bytes as f64 / (1 << 20) as f64;
+            println!(
+                "Parsed {} rows, {:.1} MiB in {:.2}s ({:.1} MiB/s, parse threads: {})",
+                data.len(),
+                mib,
+                secs,
+                mib / secs,
+                parse_threads()
+            );
+        }
+        Ok(data)
+    }
 
-        // Read the file line by line using the lines() iterator from std::io::BufRead.
-        let mut n_lines: u64 = 0;
-        for line in reader.lines() {
-            n_lines = n_lines + 1;
-            let line = line.unwrap();
-            let mut vals: Vec<&str> = line.split(" ").collect();
-
-            let nonce = vals.pop().unwrap();
-            let header = vals.pop().unwrap();
-
-            let values: BlockHeaderData = BlockHeaderData {
-                nonce: import_bin_string(nonce),
-                header: import_bin_string(header),
+    /// Reads a headers file a chunk of rows at a time, with the checks of
+    /// `import_headers_file`, so that a file of any size can be gone through
+    /// in bounded memory. Packed files (see `file_packed`) are read the same
+    /// way, whatever the format.
+    pub struct HeaderReader {
+        filename: String,
+        format: InputFormat,
+        source: Source,
+        // Last line read, counting from 1, or rows read from a packed file
+        pub line: u64,
+        // Bytes of text read
+        pub bytes: u64,
+        // Header and nonce widths of the first row
+        widths: Option<(usize, usize)>,
+    }
+
+    enum Source {
+        Text(BufReader<File>),
+        Packed(PackedDataset),
+    }
+
+    impl HeaderReader {
+        pub fn open(filename: String, format: InputFormat) -> Result<HeaderReader, ImportError> {
+            let source = if is_packed_file(&filename) {
+                Source::Packed(PackedDataset::open(&filename)?)
+            } else {
+                let file = File::open(&filename).map_err(|e| ImportError {
+                    file: filename.clone(),
+                    line: 0,
+                    problem: e.to_string(),
+                })?;
+                Source::Text(BufReader::new(file))
             };
-            data.push(values);
-            let n_lines_print = n_lines;
-            if n_lines_print % 10000 == 0 {
-                println!("Processing hash file line: {}", n_lines_print);
+            Ok(HeaderReader {
+                filename,
+                format,
+                source,
+                line: 0,
+                bytes: 0,
+                widths: None,
+            })
+        }
+
+        /// The description of the file, if it is a packed one
+        pub fn packed(&self) -> Option<&PackedHeader> {
+            match &self.source {
+                Source::Packed(packed) => Some(&packed.header),
+                Source::Text(_) => None,
             }
         }
-        data
+
+        fn error(&self, line: u64, problem: String) -> ImportError {
+            ImportError {
+                file: self.filename.clone(),
+                line,
+                problem,
+            }
+        }
+
+        /// Up to `rows` next rows, none at the end of the file. A file without
+        /// any row is an error.
+        pub fn next_chunk(&mut self, rows: usize) -> Result<Vec<BlockHeaderData>, ImportError> {
+            if let Source::Packed(packed) = &self.source {
+                let end = (self.line + rows as u64).min(packed.header.rows);
+                let data = packed.rows(self.line..end);
+                self.line = end;
+                if let Some(first) = data.first() {
+                    self.check_widths(first, 1)?;
+                }
+                if self.widths.is_none() {
+                    return Err(self.error(0, "no rows".to_string()));
+                }
+                return Ok(data);
+            }
+
+            let mut data: Vec<BlockHeaderData> = Vec::new();
+            let mut block: Vec<u8> = Vec::new();
+            // Blank lines make for fewer rows than lines
+            while data.len() < rows {
+                block.clear();
+                let first_line = self.line + 1;
+                let lines = self.read_lines(rows - data.len(), &mut block)?;
+                if lines == 0 {
+                    break;
+                }
+                self.line += lines;
+                self.bytes += block.len() as u64;
+                // Each part is consistent with its own first row, which has to
+                // be consistent with the first row of the file
+                for part in parse_lines(&block, first_line, self.format) {
+                    if let Some(first) = part.rows.first() {
+                        self.check_widths(first, part.first_line)?;
+                    }
+                    if let Some((line, problem)) = part.error {
+                        return Err(self.error(line, problem));
+                    }
+                    data.extend(part.rows);
+                }
+            }
+            if self.widths.is_none() {
+                return Err(self.error(0, "no rows".to_string()));
+            }
+            Ok(data)
+        }
+
+        /// Appends up to `count` whole lines to `block`, returns how many
+        fn read_lines(&mut self, count: usize, block: &mut Vec<u8>) -> Result<u64, ImportError> {
+            let reader = match &mut self.source {
+                Source::Text(reader) => reader,
+                Source::Packed(_) => return Ok(0),
+            };
+            let mut lines = 0;
+            while lines < count as u64 {
+                match reader.read_until(b'\n', block) {
+                    Ok(0) => break,
+                    Ok(_) => lines += 1,
+                    Err(e) => {
+                        return Err(ImportError {
+                            file: self.filename.clone(),
+                            line: self.line + lines + 1,
+                            problem: e.to_string(),
+                        })
+                    }
+                }
+            }
+            Ok(lines)
+        }
+
+        /// Fails if the row at `line` does not have the widths of the first row
+        /// of the file, or is the first row and has an empty header or nonce
+        fn check_widths(&mut self, row: &BlockHeaderData, line: u64) -> Result<(), ImportError> {
+            match self.widths {
+                Some((header_len, nonce_len)) => {
+                    if row.header.len() != header_len || row.nonce.len() != nonce_len {
+                        return Err(self.error(line, width_problem(row, header_len, nonce_len)));
+                    }
+                }
+                None => {
+                    if row.header.is_empty() || row.nonce.is_empty() {
+                        return Err(self.error(line, "empty header or nonce".to_string()));
+                    }
+                    self.widths = Some((row.header.len(), row.nonce.len()));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn width_problem(row: &BlockHeaderData, header_len: usize, nonce_len: usize) -> String {
+        format!(
+            "{} header and {} nonce bits, the first row has {} and {}",
+            row.header.len(),
+            row.nonce.len(),
+            header_len,
+            nonce_len
+        )
     }
 
-    fn import_bin_string(string_array: &str) -> Vec<bool> {
-        lazy_static! {
-            static ref RE: Regex = Regex::new("([10])").unwrap();
+    /// Threads parsing the text of a headers file
+    pub fn parse_threads() -> usize {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    }
+
+    /// Rows parsed from consecutive lines, up to the first problem if any
+    struct ParsedLines {
+        rows: Vec<BlockHeaderData>,
+        // Line of the first row
+        first_line: u64,
+        error: Option<(u64, String)>,
+    }
+
+    /// Parses whole lines, the first one being line `first_line` of the file.
+    /// Large blocks are split at line ends and the parts parsed in parallel,
+    /// the parts are returned in order.
+    fn parse_lines(block: &[u8], first_line: u64, format: InputFormat) -> Vec<ParsedLines> {
+        // Not worth a thread below 64 KiB
+        let parts = parse_threads().min(block.len() / (1 << 16) + 1);
+        let mut pieces = vec![];
+        let mut rest = block;
+        let mut line = first_line;
+        for part in (1..=parts).rev() {
+            let cut = match rest[rest.len() / part..].iter().position(|b| *b == b'\n') {
+                Some(end) if part > 1 => rest.len() / part + end + 1,
+                _ => rest.len(),
+            };
+            let (piece, next) = rest.split_at(cut);
+            pieces.push((piece, line));
+            line += piece.iter().filter(|b| **b == b'\n').count() as u64;
+            rest = next;
+        }
+        if pieces.len() == 1 {
+            return vec![parse_piece(block, first_line, format)];
         }
-        let mut vals: Vec<bool> = Vec::new();
-        for cap in RE.captures_iter(string_array) {
-            match &cap[0] {
-                "1" => vals.push(true),
-                "0" => vals.push(false),
-                _ => continue,
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = pieces
+                .iter()
+                .map(|(piece, line)| scope.spawn(move || parse_piece(piece, *line, format)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| {
+                    h.join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        })
+    }
+
+    /// Parses the lines of a piece of the file. The header and nonce are the
+    /// last two fields of a line, fields being separated by any whitespace.
+    fn parse_piece(piece: &[u8], first_line: u64, format: InputFormat) -> ParsedLines {
+        let mut parsed = ParsedLines {
+            rows: vec![],
+            first_line,
+            error: None,
+        };
+        let mut fields: Vec<&[u8]> = Vec::with_capacity(2);
+        for (line, text) in (first_line..).zip(piece.split(|b| *b == b'\n')) {
+            fields.clear();
+            fields.extend(
+                text.split(|b| b.is_ascii_whitespace())
+                    .filter(|f| !f.is_empty()),
+            );
+            if fields.is_empty() {
+                continue;
+            }
+            let row = match parse_fields(&fields, format) {
+                Ok(row) => row,
+                Err(problem) => {
+                    parsed.error = Some((line, problem));
+                    break;
+                }
+            };
+            match parsed.rows.first() {
+                Some(first) => {
+                    if row.header.len() != first.header.len()
+                        || row.nonce.len() != first.nonce.len()
+                    {
+                        let problem = width_problem(&row, first.header.len(), first.nonce.len());
+                        parsed.error = Some((line, problem));
+                        break;
+                    }
+                }
+                None => parsed.first_line = line,
+            }
+            parsed.rows.push(row);
+        }
+        parsed
+    }
+
+    fn parse_fields(fields: &[&[u8]], format: InputFormat) -> Result<BlockHeaderData, String> {
+        if fields.len() < 2 {
+            return Err("expected a header and a nonce separated by whitespace".to_string());
+        }
+        let header =
+            decode_field(fields[fields.len() - 2], format).map_err(|p| format!("header {}", p))?;
+        let nonce =
+            decode_field(fields[fields.len() - 1], format).map_err(|p| format!("nonce {}", p))?;
+        Ok(BlockHeaderData {
+            nonce,
+            header,
+            target: vec![],
+        })
+    }
+
+    /// Bits of a header or nonce, most significant first, failing on the
+    /// first byte that is not a digit of the format
+    pub fn decode_field(field: &[u8], format: InputFormat) -> Result<Vec<bool>, String> {
+        let mut bits = Vec::with_capacity(match format {
+            InputFormat::Bin => field.len(),
+            InputFormat::Hex => 4 * field.len(),
+        });
+        for (i, byte) in field.iter().enumerate() {
+            let invalid = || format!("has an invalid character {:?} at {}", *byte as char, i + 1);
+            match format {
+                InputFormat::Bin => match byte {
+                    b'0' => bits.push(false),
+                    b'1' => bits.push(true),
+                    _ => return Err(invalid()),
+                },
+                InputFormat::Hex => {
+                    let digit = (*byte as char).to_digit(16).ok_or_else(invalid)?;
+                    for shift in (0..4).rev() {
+                        bits.push((digit >> shift) & 1 == 1);
+                    }
+                }
+            }
+        }
+        Ok(bits)
+    }
+
+    /// Reads a list of addresses to score, one JSON object per line. Each line is
+    /// either a bare address or a line of a previous output file. Files written
+    /// before joint targets existed have a single `target_bit` or `nonce_bit`.
+    pub fn import_tuples_file(filename: String) -> Result<Vec<DataAddress>, ImportError> {
+        let error = |line: u64, problem: String| ImportError {
+            file: filename.clone(),
+            line,
+            problem,
+        };
+        let file = File::open(&filename).map_err(|e| error(0, e.to_string()))?;
+        let reader = BufReader::new(file);
+        let mut tuples: Vec<DataAddress> = Vec::new();
+
+        for (n_lines, line) in (1_u64..).zip(reader.lines()) {
+            let line = line.map_err(|e| error(n_lines, e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = json::parse(&line).map_err(|e| error(n_lines, e.to_string()))?;
+            let invalid_bit = |name: &str| error(n_lines, format!("invalid {} bit", name));
+            let address = if parsed.has_key("address") {
+                &parsed["address"]
+            } else {
+                &parsed
+            };
+
+            let header_bits: Vec<u16> = address["header"]
+                .members()
+                .map(|bit| bit.as_u16().ok_or_else(|| invalid_bit("header")))
+                .collect::<Result<_, _>>()?;
+            let target_bits: Vec<u16> = if address.has_key("target_bits") {
+                address["target_bits"]
+                    .members()
+                    .map(|bit| bit.as_u16().ok_or_else(|| invalid_bit("target")))
+                    .collect::<Result<_, _>>()?
+            } else {
+                let target_bit = if address.has_key("target_bit") {
+                    &address["target_bit"]
+                } else {
+                    &address["nonce_bit"]
+                };
+                vec![target_bit.as_u16().ok_or_else(|| invalid_bit("target"))?]
+            };
+            if header_bits.is_empty() || target_bits.is_empty() {
+                return Err(error(n_lines, "empty header or target bits".to_string()));
             }
+            tuples.push(DataAddress {
+                header_bits,
+                target_bits,
+            });
         }
-        vals
+        Ok(tuples)
     }
 
-    pub fn import_nonce_stats_file(filename: String) -> HashMap<usize, f32> {
-        let file = File::open(filename).unwrap();
+    /// Reads a checkpoint written by `save_checkpoint_to_file`
+    pub fn import_checkpoint_file(filename: String) -> Result<Checkpoint, ImportError> {
+        let error = |line: u64, problem: String| ImportError {
+            file: filename.clone(),
+            line,
+            problem,
+        };
+        let contents = std::fs::read_to_string(&filename).map_err(|e| error(0, e.to_string()))?;
+        let parsed = json::parse(&contents).map_err(|e| error(1, e.to_string()))?;
+        let invalid = |name: &str| error(1, format!("invalid {}", name));
+
+        let last_tuple = parsed["last_tuple"]
+            .members()
+            .map(|bit| bit.as_u16().ok_or_else(|| invalid("last_tuple")))
+            .collect::<Result<_, _>>()?;
+        Ok(Checkpoint {
+            hashes_file: parsed["hashes_file"]
+                .as_str()
+                .ok_or_else(|| invalid("hashes_file"))?
+                .to_string(),
+            tuple_size: parsed["tuple_size"]
+                .as_u16()
+                .ok_or_else(|| invalid("tuple_size"))?,
+            tuples_file: parsed["tuples_file"].as_str().map(|f| f.to_string()),
+            tuples_done: parsed["tuples_done"]
+                .as_u64()
+                .ok_or_else(|| invalid("tuples_done"))?,
+            last_tuple,
+        })
+    }
+
+    pub fn import_nonce_stats_file(filename: String) -> Result<HashMap<usize, f32>, ImportError> {
+        let error = |problem: String| ImportError {
+            file: filename.clone(),
+            line: 1,
+            problem,
+        };
+        let file = File::open(&filename).map_err(|e| ImportError {
+            line: 0,
+            ..error(e.to_string())
+        })?;
         let mut reader = BufReader::new(file);
         let mut data: HashMap<usize, f32> = HashMap::new();
 
@@ -57,69 +457,717 @@ pub mod file_import {
         let mut line = String::new();
         reader
             .read_line(&mut line)
-            .expect("Cannot open nonce stats file");
-        let json_object = json::parse(&line);
+            .map_err(|e| error(e.to_string()))?;
+        let json_object = json::parse(&line).map_err(|e| error(e.to_string()))?;
 
-        for (n, it) in json_object.unwrap().entries() {
+        for (n, it) in json_object.entries() {
             let mut prob_one = 0.0;
             let mut prob_zero = 0.0;
 
             for (k, vv) in it.entries() {
                 match k {
-                    "p0" => prob_zero = vv.as_f32().expect("Error extracting PZERO value"),
-                    "p1" => prob_one = vv.as_f32().expect("Error extracting PONE value"),
+                    "p0" => {
+                        prob_zero = vv
+                            .as_f32()
+                            .ok_or_else(|| error(format!("invalid p0 of bit {}", n)))?
+                    }
+                    "p1" => {
+                        prob_one = vv
+                            .as_f32()
+                            .ok_or_else(|| error(format!("invalid p1 of bit {}", n)))?
+                    }
                     _ => {}
                 }
             }
 
             let mut entropy: f32 = 0.0;
             if prob_zero > 0.0 {
-                entropy = entropy + prob_zero * prob_zero.log2();
+                entropy += prob_zero * prob_zero.log2();
             }
             if prob_one > 0.0 {
-                entropy = entropy + prob_one * prob_one.log2();
+                entropy += prob_one * prob_one.log2();
             }
-            entropy = -1.0 * entropy;
-            let mut key: usize = n.parse().unwrap();
-            key = key - 1;
+            entropy = -entropy;
+            // Bits are counted from 1 in the file
+            let key = match n.parse::<usize>() {
+                Ok(key) if key > 0 => key - 1,
+                _ => return Err(error(format!("invalid nonce bit '{}'", n))),
+            };
             data.insert(key, entropy);
         }
-        data
+        Ok(data)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::errors::errors::Error;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Tests run in parallel, each file gets a name of its own
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+
+        /// Reads the text as a headers file, in chunks of `rows` rows
+        fn import(
+            text: &str,
+            format: InputFormat,
+            rows: usize,
+        ) -> Result<Vec<BlockHeaderData>, ImportError> {
+            let file = FILES.fetch_add(1, Ordering::Relaxed);
+            let name = format!("bt-import-{}-{}.dat", std::process::id(), file);
+            let path = std::env::temp_dir().join(name);
+            let path = path.to_str().unwrap().to_string();
+            std::fs::write(&path, text).unwrap();
+            let read = HeaderReader::open(path.clone(), format).and_then(|mut reader| {
+                let mut data = vec![];
+                loop {
+                    let chunk = reader.next_chunk(rows)?;
+                    if chunk.is_empty() {
+                        return Ok(data);
+                    }
+                    data.extend(chunk);
+                }
+            });
+            std::fs::remove_file(&path).unwrap();
+            read
+        }
+
+        fn bits(text: &str) -> Vec<bool> {
+            text.bytes().map(|b| b == b'1').collect()
+        }
+
+        /// Line and problem of the error, the same whatever the chunks
+        fn error(text: &str) -> (u64, String) {
+            let errors: Vec<(u64, String)> = [1, 2, 100]
+                .iter()
+                .map(|rows| {
+                    let e = import(text, InputFormat::Bin, *rows).unwrap_err();
+                    assert_eq!(Error::from(e.clone()).exit_code(), 3);
+                    (e.line, e.problem)
+                })
+                .collect();
+            assert!(errors.iter().all(|e| *e == errors[0]), "{:?}", errors);
+            errors[0].clone()
+        }
+
+        #[test]
+        fn lines_split_on_any_whitespace() {
+            // Tabs, Windows line ends, blank lines and leading fields
+            let text = "0101\t11\r\n\n  7 0110 \t 10\n\t\n1111 00";
+            for rows in [1, 2, 100] {
+                let data = import(text, InputFormat::Bin, rows).unwrap();
+                assert_eq!(data.len(), 3);
+                assert_eq!(data[0].header, bits("0101"));
+                assert_eq!(data[0].nonce, bits("11"));
+                assert_eq!(data[1].header, bits("0110"));
+                assert_eq!(data[1].nonce, bits("10"));
+                assert_eq!(data[2].header, bits("1111"));
+            }
+            let data = import("a0 1F\n", InputFormat::Hex, 100).unwrap();
+            assert_eq!(data[0].header, bits("10100000"));
+            assert_eq!(data[0].nonce, bits("00011111"));
+        }
+
+        #[test]
+        fn width_mismatches_are_reported_with_their_line() {
+            assert_eq!(
+                error("0101 11\n\n011 10\n"),
+                (
+                    3,
+                    "3 header and 2 nonce bits, the first row has 4 and 2".to_string()
+                )
+            );
+            assert_eq!(
+                error("0101 11\n0101 11\n0101 111\n"),
+                (
+                    3,
+                    "4 header and 3 nonce bits, the first row has 4 and 2".to_string()
+                )
+            );
+        }
+
+        #[test]
+        fn malformed_lines_are_reported_with_their_line() {
+            assert_eq!(
+                error("0101 11\n010111\n"),
+                (
+                    2,
+                    "expected a header and a nonce separated by whitespace".to_string()
+                )
+            );
+            assert_eq!(
+                error("\n0101 11\n0121 10\n"),
+                (3, "header has an invalid character '2' at 3".to_string())
+            );
+            assert_eq!(
+                error("0101 1x\n"),
+                (1, "nonce has an invalid character 'x' at 2".to_string())
+            );
+            let e = import("0101 11\n01g1 10\n", InputFormat::Hex, 100).unwrap_err();
+            assert_eq!(
+                (e.line, e.problem.as_str()),
+                (2, "header has an invalid character 'g' at 3")
+            );
+        }
+
+        #[test]
+        fn files_without_rows_are_rejected() {
+            for text in ["", "\n\n", " \t\r\n"] {
+                assert_eq!(error(text), (0, "no rows".to_string()), "{:?}", text);
+            }
+        }
+
+        #[test]
+        fn fields_decode_in_either_format() {
+            assert_eq!(decode_field(b"0110", InputFormat::Bin), Ok(bits("0110")));
+            assert_eq!(decode_field(b"f0", InputFormat::Hex), Ok(bits("11110000")));
+            assert_eq!(decode_field(b"", InputFormat::Bin), Ok(vec![]));
+            assert_eq!(
+                decode_field(b"01 1", InputFormat::Bin),
+                Err("has an invalid character ' ' at 3".to_string())
+            );
+            assert_eq!(
+                decode_field(b"0x1", InputFormat::Hex),
+                Err("has an invalid character 'x' at 2".to_string())
+            );
+        }
     }
 }
 
 pub mod file_export {
-    use crate::data_structures::data_structures::Statistic;
+    use crate::data_structures::data_structures::{
+        BlockHeaderData, Checkpoint, InputFormat, RecordFormat, Statistic,
+    };
+    use crate::errors::errors::Error;
     use std::fs::File;
-    use std::io::Write;
+    use std::io::{BufWriter, Write};
     use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::{SystemTime, UNIX_EPOCH};
 
-    pub fn save_stats_to_file(stats: Vec<Statistic>) {
-        let start = SystemTime::now();
-        let since_the_epoch = start
+    // Batches finished in the same second still get a file of their own
+    static STATS_FILES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Writes header and nonce in the same layout `import_headers_file` reads
+    pub fn save_headers_to_file(
+        file_name: &str,
+        data: &[BlockHeaderData],
+        format: InputFormat,
+    ) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(file_name)?);
+        for entry in data {
+            let line = match format {
+                InputFormat::Bin => format!(
+                    "{} {}\n",
+                    bin_string(&entry.header),
+                    bin_string(&entry.nonce)
+                ),
+                InputFormat::Hex => format!(
+                    "{} {}\n",
+                    hex_string(&entry.header),
+                    hex_string(&entry.nonce)
+                ),
+            };
+            file.write_all(line.as_bytes())?;
+        }
+        file.flush()
+    }
+
+    /// Writes the nonce probabilities file `import_nonce_stats_file` reads,
+    /// with the bits counted from 1
+    pub fn save_nonce_stats_to_file(
+        file_name: &str,
+        data: &[BlockHeaderData],
+    ) -> std::io::Result<()> {
+        let nonce_len = data.first().map(|e| e.nonce.len()).unwrap_or(0);
+        let mut stats = json::JsonValue::new_object();
+        for bit in 0..nonce_len {
+            let ones = data.iter().filter(|e| e.nonce[bit]).count();
+            let zeros = data.len() - ones;
+            stats[(bit + 1).to_string()] = json::object! {
+                p0: zeros as f64 / data.len() as f64,
+                p1: ones as f64 / data.len() as f64,
+                "0": zeros,
+                "1": ones,
+            };
+        }
+        let mut file = File::create(file_name)?;
+        file.write_all(json::stringify(stats).as_bytes())?;
+        file.write_all("\n".as_bytes())
+    }
+
+    pub fn save_checkpoint_to_file(file_name: &str, checkpoint: Checkpoint) -> Result<(), Error> {
+        std::fs::write(file_name, json::stringify_pretty(checkpoint, 2) + "\n")
+            .map_err(|e| Error::Export(file_name.to_string(), e))
+    }
+
+    fn bin_string(bits: &[bool]) -> String {
+        bits.iter().map(|b| if *b { '1' } else { '0' }).collect()
+    }
+
+    /// Bits that do not fill the last hex digit are padded with zeros
+    fn hex_string(bits: &[bool]) -> String {
+        bits.chunks(4)
+            .map(|digit| {
+                let value = (0..4).fold(0u32, |acc, i| {
+                    (acc << 1) | *digit.get(i).unwrap_or(&false) as u32
+                });
+                std::char::from_digit(value, 16)
+                    .unwrap()
+                    .to_ascii_uppercase()
+            })
+            .collect()
+    }
+
+    /// Writes the best statistics of a top-K run, best first, to
+    /// `experiment04_<kind>_<time>.json`
+    pub fn save_top_stats_to_file(
+        kind: &str,
+        stats: Vec<Statistic>,
+        format: &RecordFormat,
+    ) -> Result<(), Error> {
+        let since_the_epoch = SystemTime::now()
             .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let file_name = format!("experiment04_{}.json", since_the_epoch.as_secs().to_string());
+            .unwrap_or_default();
+        let file_name = format!("experiment04_{}_{}.json", kind, since_the_epoch.as_secs());
+        let error = |e| Error::Export(file_name.clone(), e);
+        let mut file = BufWriter::new(File::create(&file_name).map_err(error)?);
+        println!(
+            "Writing the {} best statistics to file {}",
+            stats.len(),
+            file_name
+        );
+        for s in stats {
+            writeln!(file, "{}", json::stringify(s.into_json(format))).map_err(error)?;
+        }
+        file.flush().map_err(error)
+    }
+
+    pub fn save_stats_to_file(stats: Vec<Statistic>, format: &RecordFormat) -> Result<(), Error> {
+        let start = SystemTime::now();
+        // A clock before the epoch only makes for an odd file name
+        let since_the_epoch = start.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let file_name = format!(
+            "experiment04_{}_{}.json",
+            since_the_epoch.as_secs(),
+            STATS_FILES.fetch_add(1, Ordering::SeqCst)
+        );
 
         let path = Path::new(&file_name);
         let display = path.display();
+        let error = |e| Error::Export(file_name.clone(), e);
 
         // Open a file in write-only mode, returns `io::Result<File>`
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("couldn't create {}: {}", display, why),
-            Ok(file) => file,
-        };
+        let mut file = File::create(path).map_err(error)?;
 
         println!("Dumping data to file {}", display);
         for s in stats {
-            let val = json::stringify(s);
-            match file.write_all(val.as_bytes()) {
-                Err(why) => panic!("couldn't write to {}: {}", display, why),
-                _ => {}
+            let val = json::stringify(s.into_json(format));
+            file.write_all(val.as_bytes()).map_err(error)?;
+            file.write_all("\n".as_bytes()).map_err(error)?;
+        }
+        Ok(())
+    }
+}
+
+pub mod file_packed {
+    //! Datasets converted once from a text hashes file into a binary file that
+    //! is memory mapped instead of parsed. The file starts with `MAGIC`, the
+    //! length of a JSON description as a little-endian u64 and the description:
+    //! row count, bit widths, the text file it was converted from with its
+    //! SHA-256, and the columns of each header field. The bits follow from the
+    //! next multiple of 8 bytes, a column per header bit then per nonce bit.
+    //! Each column packs the rows in little-endian u64 words, row `r` being bit
+    //! `r % 64` of word `r / 64`.
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::errors::errors::Error;
+    use crate::features::sha256::Hasher;
+    use crate::file::file_import::ImportError;
+    use crate::header::layout::HEADER_FIELDS;
+    use json::JsonValue;
+    use memmap2::Mmap;
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::{BufWriter, Read, Write};
+    use std::ops::Range;
+
+    pub const MAGIC: &[u8; 8] = b"BTPACK01";
+
+    /// The description at the start of a packed file
+    #[derive(Debug, Clone)]
+    pub struct PackedHeader {
+        pub rows: u64,
+        pub header_bits: usize,
+        pub nonce_bits: usize,
+        pub source_file: String,
+        pub source_sha256: String,
+        // Name, first column and number of columns, the nonce included
+        pub fields: Vec<(String, usize, usize)>,
+    }
+
+    impl PackedHeader {
+        /// u64 words of each column
+        pub fn column_words(&self) -> usize {
+            self.rows.div_ceil(64) as usize
+        }
+
+        /// What is announced when the file is mapped
+        pub fn describe(&self, filename: &str) -> String {
+            format!(
+                "Mapping packed dataset {}, {} rows converted from {} (sha256 {})",
+                filename, self.rows, self.source_file, self.source_sha256
+            )
+        }
+    }
+
+    impl From<PackedHeader> for JsonValue {
+        fn from(h: PackedHeader) -> Self {
+            let mut fields = JsonValue::new_array();
+            for (name, start, len) in h.fields {
+                fields
+                    .push(json::object! { name: name, start: start, len: len })
+                    .expect("Error inserting JSON");
+            }
+            json::object! {
+                rows: h.rows,
+                header_bits: h.header_bits,
+                nonce_bits: h.nonce_bits,
+                source: json::object! {
+                    file: h.source_file,
+                    sha256: h.source_sha256,
+                },
+                fields: fields,
+            }
+        }
+    }
+
+    /// Whether the file starts like a packed file
+    pub fn is_packed_file(filename: &str) -> bool {
+        let mut magic = [0u8; 8];
+        match File::open(filename) {
+            Ok(mut file) => file.read_exact(&mut magic).is_ok() && magic == *MAGIC,
+            Err(_) => false,
+        }
+    }
+
+    /// SHA-256 of a file, in hex, read a piece at a time
+    pub fn file_sha256(filename: &str) -> Result<String, ImportError> {
+        let error = |e: std::io::Error| ImportError {
+            file: filename.to_string(),
+            line: 0,
+            problem: e.to_string(),
+        };
+        let mut file = File::open(filename).map_err(error)?;
+        let mut hasher = Hasher::new();
+        let mut buffer = vec![0u8; 1 << 20];
+        loop {
+            let read = file.read(&mut buffer).map_err(error)?;
+            if read == 0 {
+                break;
             }
-            file.write_all("\n".as_bytes())
-                .expect("Cannot write to file!");
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hasher
+            .finish()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    /// Writes the rows as a packed file, returns its size in bytes
+    pub fn save_packed_file(
+        file_name: &str,
+        data: &[BlockHeaderData],
+        source_file: &str,
+        source_sha256: &str,
+    ) -> Result<u64, Error> {
+        let (header_bits, nonce_bits) = match data.first() {
+            Some(entry) => (entry.header.len(), entry.nonce.len()),
+            None => (0, 0),
+        };
+        // The header fields the rows have in full, or the header as a whole
+        let mut fields: Vec<(String, usize, usize)> = HEADER_FIELDS
+            .iter()
+            .filter(|f| (f.start + f.len) as usize <= header_bits)
+            .map(|f| (f.name.to_string(), f.start as usize, f.len as usize))
+            .collect();
+        if fields.is_empty() {
+            fields.push(("header".to_string(), 0, header_bits));
+        }
+        fields.push(("nonce".to_string(), header_bits, nonce_bits));
+        let header = PackedHeader {
+            rows: data.len() as u64,
+            header_bits,
+            nonce_bits,
+            source_file: source_file.to_string(),
+            source_sha256: source_sha256.to_string(),
+            fields,
+        };
+
+        let words = header.column_words();
+        let mut columns = vec![0u64; (header_bits + nonce_bits) * words];
+        for (row, entry) in data.iter().enumerate() {
+            for (column, bit) in entry.header.iter().chain(&entry.nonce).enumerate() {
+                columns[column * words + row / 64] |= (*bit as u64) << (row % 64);
+            }
+        }
+
+        let description = json::stringify(header);
+        let start = MAGIC.len() + 8 + description.len();
+        let padding = start.next_multiple_of(8) - start;
+        let error = |e| Error::Export(file_name.to_string(), e);
+        let mut file = BufWriter::new(File::create(file_name).map_err(error)?);
+        file.write_all(MAGIC).map_err(error)?;
+        file.write_all(&(description.len() as u64).to_le_bytes())
+            .map_err(error)?;
+        file.write_all(description.as_bytes()).map_err(error)?;
+        file.write_all(&vec![0u8; padding]).map_err(error)?;
+        for word in &columns {
+            file.write_all(&word.to_le_bytes()).map_err(error)?;
+        }
+        file.flush().map_err(error)?;
+        Ok((start + padding + columns.len() * 8) as u64)
+    }
+
+    /// A packed file, mapped in memory
+    #[derive(Debug)]
+    pub struct PackedDataset {
+        pub header: PackedHeader,
+        map: Mmap,
+        data_offset: usize,
+    }
+
+    impl PackedDataset {
+        pub fn open(filename: &str) -> Result<PackedDataset, ImportError> {
+            let error = |problem: String| ImportError {
+                file: filename.to_string(),
+                line: 0,
+                problem,
+            };
+            let file = File::open(filename).map_err(|e| error(e.to_string()))?;
+            // Safe as long as nobody changes the file while it is analyzed
+            let map = unsafe { Mmap::map(&file) }.map_err(|e| error(e.to_string()))?;
+            if map.len() < 16 || map[..8] != *MAGIC {
+                return Err(error("not a packed dataset".to_string()));
+            }
+            let mut length = [0u8; 8];
+            length.copy_from_slice(&map[8..16]);
+            // The length is read from the file, it may be anything
+            let end = usize::try_from(u64::from_le_bytes(length))
+                .ok()
+                .and_then(|length| length.checked_add(16))
+                .filter(|end| *end <= map.len())
+                .ok_or_else(|| error("truncated description".to_string()))?;
+            let description = std::str::from_utf8(&map[16..end])
+                .map_err(|_| error("description is not UTF-8".to_string()))?;
+            let header = parse_header(description).map_err(error)?;
+
+            // The columns are used as they are mapped, as u64 words
+            if cfg!(target_endian = "big") || map.as_ptr().align_offset(8) != 0 {
+                return Err(error(
+                    "packed datasets are only mapped on little-endian machines".to_string(),
+                ));
+            }
+            let data_offset = end.next_multiple_of(8);
+            let columns = header.header_bits.saturating_add(header.nonce_bits);
+            let size = columns
+                .checked_mul(header.column_words())
+                .and_then(|words| words.checked_mul(8))
+                .and_then(|bytes| bytes.checked_add(data_offset));
+            if size.is_none_or(|size| map.len() < size) {
+                return Err(error(format!(
+                    "truncated, {} bytes for {} rows of {} bits",
+                    map.len(),
+                    header.rows,
+                    columns
+                )));
+            }
+            Ok(PackedDataset {
+                header,
+                map,
+                data_offset,
+            })
+        }
+
+        /// The words of a column, the header bits first, then the nonce bits
+        pub fn column(&self, column: usize) -> &[u64] {
+            let words = self.header.column_words();
+            let start = self.data_offset + column * words * 8;
+            // Aligned and little-endian, checked when the file was opened
+            let (_, column, _) = unsafe { self.map[start..start + words * 8].align_to::<u64>() };
+            column
+        }
+
+        /// The rows of the given range, as if read from the text file
+        pub fn rows(&self, range: Range<u64>) -> Vec<BlockHeaderData> {
+            let (header_bits, nonce_bits) = (self.header.header_bits, self.header.nonce_bits);
+            let mut rows: Vec<BlockHeaderData> = range
+                .clone()
+                .map(|_| BlockHeaderData {
+                    nonce: Vec::with_capacity(nonce_bits),
+                    header: Vec::with_capacity(header_bits),
+                    target: vec![],
+                })
+                .collect();
+            // A column at a time, the way they are laid out
+            for column in 0..header_bits + nonce_bits {
+                let words = self.column(column);
+                for (row, entry) in range.clone().zip(rows.iter_mut()) {
+                    let row = row as usize;
+                    let bit = (words[row / 64] >> (row % 64)) & 1 == 1;
+                    if column < header_bits {
+                        entry.header.push(bit);
+                    } else {
+                        entry.nonce.push(bit);
+                    }
+                }
+            }
+            rows
+        }
+    }
+
+    fn parse_header(description: &str) -> Result<PackedHeader, String> {
+        let parsed = json::parse(description).map_err(|e| e.to_string())?;
+        let invalid = |name: &str| format!("invalid {}", name);
+        let fields = parsed["fields"]
+            .members()
+            .map(|f| {
+                match (
+                    f["name"].as_str(),
+                    f["start"].as_usize(),
+                    f["len"].as_usize(),
+                ) {
+                    (Some(name), Some(start), Some(len)) => Ok((name.to_string(), start, len)),
+                    _ => Err(invalid("fields")),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PackedHeader {
+            rows: parsed["rows"].as_u64().ok_or_else(|| invalid("rows"))?,
+            header_bits: parsed["header_bits"]
+                .as_usize()
+                .ok_or_else(|| invalid("header_bits"))?,
+            nonce_bits: parsed["nonce_bits"]
+                .as_usize()
+                .ok_or_else(|| invalid("nonce_bits"))?,
+            source_file: parsed["source"]["file"]
+                .as_str()
+                .ok_or_else(|| invalid("source file"))?
+                .to_string(),
+            source_sha256: parsed["source"]["sha256"]
+                .as_str()
+                .ok_or_else(|| invalid("source sha256"))?
+                .to_string(),
+            fields,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::generator::synthetic::generate_dataset;
+
+        fn path(name: &str) -> String {
+            let path = std::env::temp_dir().join(format!("{}-{}.btp", name, std::process::id()));
+            path.to_str().unwrap().to_string()
+        }
+
+        /// Opens the file and removes it, the mapping outlives the name
+        fn open(path: &str) -> Result<PackedDataset, ImportError> {
+            let packed = PackedDataset::open(path);
+            std::fs::remove_file(path).unwrap();
+            packed
+        }
+
+        /// The bytes of a packed file of the rows
+        fn packed_bytes(rows: &[BlockHeaderData], name: &str) -> Vec<u8> {
+            let path = path(name);
+            save_packed_file(&path, rows, "rows.dat", "abc").unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            bytes
+        }
+
+        fn problem(bytes: &[u8], name: &str) -> String {
+            let path = path(name);
+            std::fs::write(&path, bytes).unwrap();
+            match open(&path) {
+                Err(e) => {
+                    assert_eq!(e.file, path);
+                    e.problem
+                }
+                Ok(_) => panic!("{} opened", name),
+            }
+        }
+
+        #[test]
+        fn packed_files_reopen_with_their_rows() {
+            // Full block headers, and a row count that is not a multiple of 64
+            let rows = generate_dataset(130, 608, 32, &[], 3).unwrap();
+            let path = path("bt-packed-roundtrip");
+            let size = save_packed_file(&path, &rows, "rows.dat", "abc").unwrap();
+            assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+            assert!(is_packed_file(&path));
+            let packed = open(&path).unwrap();
+            assert_eq!(packed.header.rows, 130);
+            assert_eq!(packed.header.header_bits, 608);
+            assert_eq!(packed.header.nonce_bits, 32);
+            assert_eq!(packed.header.source_file, "rows.dat");
+            assert_eq!(packed.header.source_sha256, "abc");
+            assert_eq!(packed.header.fields[3], ("time".to_string(), 544, 32));
+            assert_eq!(packed.header.fields.last().unwrap().0, "nonce");
+            let read = packed.rows(0..130);
+            for (read, row) in read.iter().zip(&rows) {
+                assert_eq!(read.header, row.header);
+                assert_eq!(read.nonce, row.nonce);
+            }
+            assert_eq!(packed.rows(64..70)[1].header, rows[65].header);
+            // Bit r % 64 of word r / 64
+            let column = packed.column(608);
+            assert_eq!(column.len(), 3);
+            assert_eq!((column[2] >> 1) & 1 == 1, rows[129].nonce[0]);
+        }
+
+        #[test]
+        fn truncated_and_foreign_files_are_rejected() {
+            let rows = generate_dataset(100, 40, 8, &[], 3).unwrap();
+            let bytes = packed_bytes(&rows, "bt-packed-bytes");
+
+            let mut foreign = bytes.clone();
+            foreign[..8].copy_from_slice(b"BTPACK02");
+            assert_eq!(problem(&foreign, "bt-packed-magic"), "not a packed dataset");
+            assert_eq!(
+                problem(&bytes[..10], "bt-packed-short"),
+                "not a packed dataset"
+            );
+
+            // Cut in the description, and in the columns
+            let start = bytes.len() - 48 * 2 * 8;
+            assert_eq!(
+                problem(&bytes[..40], "bt-packed-cut"),
+                "truncated description"
+            );
+            assert_eq!(
+                problem(&bytes[..start + 8], "bt-packed-columns"),
+                format!("truncated, {} bytes for 100 rows of 48 bits", start + 8)
+            );
+
+            // A description length past the end, or past any size
+            for length in [bytes.len() as u64, u64::MAX - 8, u64::MAX] {
+                let mut crafted = bytes.clone();
+                crafted[8..16].copy_from_slice(&length.to_le_bytes());
+                assert_eq!(
+                    problem(&crafted, "bt-packed-length"),
+                    "truncated description"
+                );
+            }
+
+            // Widths whose columns do not fit in memory
+            let description = r#"{"rows":1152921504606846976,"header_bits":1099511627776,"nonce_bits":1,"source":{"file":"x","sha256":""},"fields":[]}"#;
+            let mut crafted = MAGIC.to_vec();
+            crafted.extend_from_slice(&(description.len() as u64).to_le_bytes());
+            crafted.extend_from_slice(description.as_bytes());
+            assert!(problem(&crafted, "bt-packed-widths").starts_with("truncated, "));
         }
     }
 }
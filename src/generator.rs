@@ -0,0 +1,346 @@
+pub mod random {
+    /// Small seedable generator (SplitMix64), good enough for test data and
+    /// reproducible across platforms
+    #[derive(Debug, Clone)]
+    pub struct Rng {
+        state: u64,
+    }
+
+    impl Rng {
+        pub fn new(seed: u64) -> Rng {
+            Rng { state: seed }
+        }
+
+        pub fn next_u64(&mut self) -> u64 {
+            self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = self.state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+
+        pub fn next_bool(&mut self) -> bool {
+            self.next_u64() >> 63 == 1
+        }
+
+        /// Uniform in [0, 1)
+        pub fn next_f64(&mut self) -> f64 {
+            (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+}
+
+pub mod synthetic {
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::generator::random::Rng;
+    use json::JsonValue;
+
+    /// A dependency planted in the generated data: the nonce bit is equal to
+    /// the XOR of the header bits with the given probability, and to its
+    /// complement otherwise. A probability of 0.5 plants nothing.
+    #[derive(Debug, Clone)]
+    pub struct PlantedDependency {
+        pub nonce_bit: u16,
+        pub header_bits: Vec<u16>,
+        pub probability: f64,
+    }
+
+    impl From<PlantedDependency> for JsonValue {
+        fn from(p: PlantedDependency) -> Self {
+            // Same layout as the addresses of the output, so the file can be
+            // given back to --tuples
+            json::object! {
+                header: p.header_bits,
+                target_bits: vec![p.nonce_bit],
+                probability: p.probability,
+            }
+        }
+    }
+
+    /// Parses `<nonce bit>=<header bit>^<header bit>...@<probability>`,
+    /// e.g. `3=100^200@0.6`. Without `@` the dependency always holds.
+    pub fn parse_planted(spec: &str) -> Result<PlantedDependency, String> {
+        let invalid = || format!("Invalid planted dependency '{}'", spec);
+        let (nonce_bit, rest) = spec.split_once('=').ok_or_else(invalid)?;
+        let (expression, probability) = match rest.split_once('@') {
+            Some((expression, probability)) => (
+                expression,
+                probability.trim().parse::<f64>().map_err(|_| invalid())?,
+            ),
+            None => (rest, 1.0),
+        };
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(invalid());
+        }
+        let mut header_bits = vec![];
+        for bit in expression.split('^') {
+            header_bits.push(bit.trim().parse::<u16>().map_err(|_| invalid())?);
+        }
+        Ok(PlantedDependency {
+            nonce_bit: nonce_bit.trim().parse::<u16>().map_err(|_| invalid())?,
+            header_bits,
+            probability,
+        })
+    }
+
+    /// Random headers and nonces, every bit independent and uniform, except
+    /// for the planted nonce bits
+    pub fn generate_dataset(
+        rows: usize,
+        header_bits: u16,
+        nonce_bits: u16,
+        planted: &[PlantedDependency],
+        seed: u64,
+    ) -> Result<Vec<BlockHeaderData>, String> {
+        for p in planted {
+            if p.nonce_bit >= nonce_bits || p.header_bits.iter().any(|b| *b >= header_bits) {
+                return Err(format!(
+                    "Planted dependency {:?} is out of {} header and {} nonce bits",
+                    p, header_bits, nonce_bits
+                ));
+            }
+        }
+
+        let mut rng = Rng::new(seed);
+        let mut data = Vec::with_capacity(rows);
+        for _ in 0..rows {
+            let header: Vec<bool> = (0..header_bits).map(|_| rng.next_bool()).collect();
+            let mut nonce: Vec<bool> = (0..nonce_bits).map(|_| rng.next_bool()).collect();
+            for p in planted {
+                let parity = p
+                    .header_bits
+                    .iter()
+                    .fold(false, |acc, b| acc ^ header[*b as usize]);
+                nonce[p.nonce_bit as usize] = if rng.next_f64() < p.probability {
+                    parity
+                } else {
+                    !parity
+                };
+            }
+            data.push(BlockHeaderData {
+                nonce,
+                header,
+                target: vec![],
+            });
+        }
+        Ok(data)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::data_structures::data_structures::{CountingKernel, Scoring, ScoringMode};
+        use crate::dataset::dataset::Dataset;
+        use crate::statistics::computation::{account_address, score_statistics};
+        use crate::statistics::selection::measure_target_entropies;
+        use std::collections::HashMap;
+
+        type Address = (Vec<u16>, Vec<u16>);
+
+        /// The header and target bits that pass the thresholds, out of the
+        /// given ones, in data with `3=10^20@0.8` planted
+        fn passing(mode: ScoringMode, addresses: &[Address]) -> Vec<Address> {
+            let planted = parse_planted("3=10^20@0.8").unwrap();
+            let mut rows = generate_dataset(2000, 32, 8, &[planted], 1).unwrap();
+            for row in rows.iter_mut() {
+                row.target = row.nonce.clone();
+            }
+            let data = Dataset::Memory(rows);
+            let groups: Vec<Vec<u16>> = addresses.iter().map(|(_, t)| t.clone()).collect();
+            let entropies = measure_target_entropies(&data, &groups, mode).unwrap();
+            let scoring = Scoring {
+                mode,
+                kernel: CountingKernel::Auto,
+                target_entropies: groups.into_iter().zip(entropies).collect::<HashMap<_, _>>(),
+                sample_threshold: 10,
+                info_threshold: 0.05,
+                max_p_value: 0.001,
+                top: None,
+            };
+            let mut stats = vec![];
+            for (header, target) in addresses {
+                account_address(header, target, mode, &mut stats);
+            }
+            score_statistics(&data, stats, &scoring)
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.address.header_bits, s.address.target_bits))
+                .collect()
+        }
+
+        #[test]
+        fn planted_dependency_is_found() {
+            let addresses = [
+                (vec![10, 20], vec![3]),
+                // The right header bits with another nonce bit, and the other
+                // way round
+                (vec![10, 20], vec![4]),
+                (vec![10, 21], vec![3]),
+                (vec![11, 20], vec![3]),
+            ];
+            for mode in [ScoringMode::Table, ScoringMode::Parity] {
+                let expected = vec![(vec![10, 20], vec![3])];
+                assert_eq!(passing(mode, &addresses), expected, "{:?}", mode);
+            }
+        }
+    }
+}
+
+pub mod mining {
+    //! Mines blocks on a chain of its own, at a low difficulty, to get header and
+    //! nonce pairs whose nonces come from a known search strategy.
+    use crate::data_structures::data_structures::BlockHeaderData;
+    use crate::features::pow::{compact_to_target, expected_hashes, hash_meets_target};
+    use crate::features::sha256::{compress, digest, padded_chunks, INITIAL_STATE};
+    use crate::generator::random::Rng;
+    use crate::header::layout::bytes_to_bits;
+
+    /// How a miner walks the nonce space of each header
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum NonceStrategy {
+        /// 0, 1, 2... like a single CPU miner
+        Sequential,
+        /// Sequential from a random starting nonce
+        RandomStart,
+        /// The nonce space split in equal ranges, one per thread, all threads
+        /// advancing one nonce at a time from the start of their range
+        Threads(u32),
+    }
+
+    pub fn parse_strategy(spec: &str) -> Result<NonceStrategy, String> {
+        match spec.split_once(':') {
+            None if spec == "sequential" => Ok(NonceStrategy::Sequential),
+            None if spec == "random" => Ok(NonceStrategy::RandomStart),
+            Some(("threads", n)) => match n.parse::<u32>() {
+                Ok(n) if n > 0 => Ok(NonceStrategy::Threads(n)),
+                _ => Err(format!("Invalid number of threads '{}'", n)),
+            },
+            _ => Err(format!("Unknown nonce strategy '{}'", spec)),
+        }
+    }
+
+    /// Hardest target accepted, in expected hashes per block: half a minute
+    /// of mining each at most. 1e00ffff needs 2^24 hashes, the minimum
+    /// difficulty of Bitcoin, 1d00ffff, 2^32.
+    pub const MAX_EXPECTED_HASHES: f64 = (1u64 << 26) as f64;
+
+    /// Hashes tried for a block, over all its headers, before giving up, as a
+    /// multiple of the expected number. Reaching it by chance is as likely as
+    /// e^-64.
+    const ATTEMPTS_PER_EXPECTED_HASH: f64 = 64.0;
+
+    #[derive(Debug, Clone)]
+    pub struct MiningParameters {
+        pub blocks: usize,
+        pub version: u32,
+        pub bits: u32,
+        pub start_time: u32,
+        pub strategy: NonceStrategy,
+        pub seed: u64,
+    }
+
+    /// Mines `blocks` blocks, each one on top of the previous one. The merkle
+    /// root is random and the time moves forward between 1 and 1200 seconds
+    /// per block. A header that runs out of nonces is dropped and a new one,
+    /// with a new merkle root and time, is tried instead. Fails on a block
+    /// not found in 64 times the expected number of hashes.
+    pub fn mine_chain(params: &MiningParameters) -> Result<Vec<BlockHeaderData>, String> {
+        let max_attempts =
+            (ATTEMPTS_PER_EXPECTED_HASH * expected_hashes(params.bits)?).ceil() as u64;
+        let target = compact_to_target(params.bits);
+        let mut attempts = max_attempts;
+        let mut rng = Rng::new(params.seed);
+        let mut prev_hash = [0u8; 32];
+        let mut time = params.start_time;
+        let mut data = Vec::with_capacity(params.blocks);
+
+        while data.len() < params.blocks {
+            let mut merkle_root = [0u8; 32];
+            for byte in merkle_root.iter_mut() {
+                *byte = rng.next_u64() as u8;
+            }
+            time = time.wrapping_add(1 + (rng.next_u64() % 1200) as u32);
+
+            let mut header = Vec::with_capacity(80);
+            header.extend_from_slice(&params.version.to_le_bytes());
+            header.extend_from_slice(&prev_hash);
+            header.extend_from_slice(&merkle_root);
+            header.extend_from_slice(&time.to_le_bytes());
+            header.extend_from_slice(&params.bits.to_le_bytes());
+            header.extend_from_slice(&[0u8; 4]);
+
+            let found = search_nonce(&header, &target, params.strategy, &mut rng, &mut attempts);
+            if let Some((nonce, hash)) = found {
+                header[76..80].copy_from_slice(&nonce.to_le_bytes());
+                data.push(BlockHeaderData {
+                    nonce: bytes_to_bits(&header[76..80]),
+                    header: bytes_to_bits(&header[..76]),
+                    target: vec![],
+                });
+                prev_hash = hash;
+                attempts = max_attempts;
+                if data.len().is_multiple_of(1000) {
+                    println!("Mined block {}", data.len());
+                }
+            } else if attempts == 0 {
+                return Err(format!(
+                    "No block {} found in {} hashes at target {:08x}",
+                    data.len() + 1,
+                    max_attempts,
+                    params.bits
+                ));
+            }
+        }
+        Ok(data)
+    }
+
+    /// First nonce found by the strategy whose block hash meets the target,
+    /// with that hash, trying at most `attempts` nonces and taking the ones
+    /// tried off it. The first chunk of the header does not depend on the
+    /// nonce, so it is compressed once and only the second chunk per nonce.
+    fn search_nonce(
+        header: &[u8],
+        target: &[u8; 32],
+        strategy: NonceStrategy,
+        rng: &mut Rng,
+        attempts: &mut u64,
+    ) -> Option<(u32, [u8; 32])> {
+        let mut chunks = padded_chunks(header);
+        let mut midstate = INITIAL_STATE;
+        compress(&mut midstate, &chunks[0]);
+
+        let mut try_nonce = |nonce: u32| {
+            // The nonce is in bytes 76..80 of the header, 12..16 of the second chunk
+            chunks[1][12..16].copy_from_slice(&nonce.to_le_bytes());
+            let mut state = midstate;
+            compress(&mut state, &chunks[1]);
+            let mut first = [0u8; 32];
+            for (i, word) in state.iter().enumerate() {
+                first[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+            }
+            let hash = digest(&first);
+            if hash_meets_target(&hash, target) {
+                Some((nonce, hash))
+            } else {
+                None
+            }
+        };
+
+        let start = match strategy {
+            NonceStrategy::RandomStart => (rng.next_u64() >> 32) as u32,
+            _ => 0,
+        };
+        let threads = match strategy {
+            NonceStrategy::Threads(threads) => threads as u64,
+            _ => 1,
+        };
+        // Nonce tried at step i: the threads take one nonce each in turn, each
+        // from the start of its range
+        let range = (1u64 << 32) / threads;
+        let nonce_at = |i: u64| start.wrapping_add(((i % threads) * range + i / threads) as u32);
+        let steps = (range * threads).min(*attempts);
+        let found = (0..steps).find_map(|i| try_nonce(nonce_at(i)).map(|found| (i, found)));
+        *attempts -= found.map_or(steps, |(i, _)| i + 1);
+        found.map(|(_, found)| found)
+    }
+}
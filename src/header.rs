@@ -0,0 +1,302 @@
+pub mod layout {
+    use json::JsonValue;
+    use std::fmt;
+
+    /// A field of the serialized block header, as a range of header bits.
+    /// The nonce is not part of the header bits, it is the value we correlate against.
+    /// Derived features are laid out after the header, see `features::derived`.
+    #[derive(Debug, Clone)]
+    pub struct HeaderField {
+        pub name: &'static str,
+        pub start: u16,
+        pub len: u16,
+    }
+
+    /// Bits in the serialized header without the nonce
+    pub const HEADER_BITS: u16 = 608;
+
+    pub const HEADER_FIELDS: [HeaderField; 7] = [
+        HeaderField {
+            name: "version",
+            start: 0,
+            len: 32,
+        },
+        HeaderField {
+            name: "prev_hash",
+            start: 32,
+            len: 256,
+        },
+        HeaderField {
+            name: "merkle_root",
+            start: 288,
+            len: 256,
+        },
+        HeaderField {
+            name: "time",
+            start: 544,
+            len: 32,
+        },
+        HeaderField {
+            name: "bits",
+            start: 576,
+            len: 32,
+        },
+        // SHA-256 state after the first chunk, as eight big-endian words
+        HeaderField {
+            name: "midstate",
+            start: 608,
+            len: 256,
+        },
+        // W16 and W17 of the second chunk's message schedule, big-endian words
+        HeaderField {
+            name: "schedule",
+            start: 864,
+            len: 64,
+        },
+    ];
+
+    pub fn find_field(name: &str) -> Option<&'static HeaderField> {
+        HEADER_FIELDS.iter().find(|f| f.name == name)
+    }
+
+    /// Where a header bit lives in the serialized header. `byte` counts from the
+    /// start of the field in serialization order and `bit` is the bit inside that
+    /// byte, 0 being the least significant one. The header bits store each byte
+    /// most significant bit first, and the integer fields (version, time, bits)
+    /// are little-endian, so for those `byte * 8 + bit` is the bit of the value.
+    /// Hashes are stored in internal byte order, the reverse of the usual hex.
+    /// Derived fields hold big-endian words, so their bytes are big-endian too.
+    #[derive(Debug, Clone)]
+    pub struct BitLocation {
+        pub field: &'static str,
+        pub byte: u16,
+        pub bit: u8,
+    }
+
+    impl fmt::Display for BitLocation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}[{}].{}", self.field, self.byte, self.bit)
+        }
+    }
+
+    impl From<BitLocation> for JsonValue {
+        fn from(l: BitLocation) -> Self {
+            json::object! {
+                field: l.field,
+                byte: l.byte,
+                bit: l.bit,
+            }
+        }
+    }
+
+    pub fn locate_bit(index: u16) -> Option<BitLocation> {
+        let field = HEADER_FIELDS
+            .iter()
+            .find(|f| index >= f.start && index < f.start + f.len)?;
+        let offset = index - field.start;
+        Some(BitLocation {
+            field: field.name,
+            byte: offset / 8,
+            bit: 7 - (offset % 8) as u8,
+        })
+    }
+
+    /// Index of a bit given by its location, the inverse of `locate_bit`
+    pub fn bit_index(field: &HeaderField, byte: u16, bit: u8) -> Result<u16, String> {
+        if bit > 7 || byte >= field.len / 8 {
+            return Err(format!(
+                "{}[{}].{} is outside the field ({} bytes)",
+                field.name,
+                byte,
+                bit,
+                field.len / 8
+            ));
+        }
+        Ok(field.start + byte * 8 + (7 - bit as u16))
+    }
+
+    /// Parses a comma separated list of header bits. Each item is either a bit
+    /// index (`336`), an inclusive range (`544-575`), a field name (`time`), a
+    /// byte of a field (`time[0]`), a range of bytes (`merkle_root[0-3]`) or a
+    /// single bit of a field (`time[0].0`, see `BitLocation`).
+    /// The result is sorted and without duplicates.
+    pub fn parse_bit_mask(spec: &str) -> Result<Vec<u16>, String> {
+        let mut bits: Vec<u16> = vec![];
+        for item in spec.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+            if let Some(field) = find_field(item) {
+                bits.extend(field.start..field.start + field.len);
+            } else if let Some((name, rest)) = item.split_once('[') {
+                let field =
+                    find_field(name).ok_or_else(|| format!("Unknown header field '{}'", name))?;
+                bits.extend(parse_field_selector(field, rest)?);
+            } else if let Some((from, to)) = item.split_once('-') {
+                let from = parse_bit(from)?;
+                let to = parse_bit(to)?;
+                if from > to {
+                    return Err(format!("Empty bit range {}", item));
+                }
+                bits.extend(from..=to);
+            } else {
+                bits.push(parse_bit(item)?);
+            }
+        }
+        bits.sort_unstable();
+        bits.dedup();
+        Ok(bits)
+    }
+
+    /// Parses the part after the field name: `3]`, `0-3]` or `3].7`
+    fn parse_field_selector(field: &HeaderField, selector: &str) -> Result<Vec<u16>, String> {
+        let invalid = || format!("Invalid selector '{}[{}'", field.name, selector);
+        let (bytes, bit) = selector.split_once(']').ok_or_else(invalid)?;
+        let (first_byte, last_byte) = match bytes.split_once('-') {
+            Some((from, to)) => (from.trim(), to.trim()),
+            None => (bytes.trim(), bytes.trim()),
+        };
+        let first_byte = first_byte.parse::<u16>().map_err(|_| invalid())?;
+        let last_byte = last_byte.parse::<u16>().map_err(|_| invalid())?;
+
+        let mut bits = vec![];
+        if bit.is_empty() {
+            for byte in first_byte..=last_byte {
+                for b in 0..8 {
+                    bits.push(bit_index(field, byte, b)?);
+                }
+            }
+        } else {
+            let bit = bit
+                .strip_prefix('.')
+                .and_then(|b| b.parse::<u8>().ok())
+                .ok_or_else(invalid)?;
+            for byte in first_byte..=last_byte {
+                bits.push(bit_index(field, byte, bit)?);
+            }
+        }
+        Ok(bits)
+    }
+
+    fn parse_bit(value: &str) -> Result<u16, String> {
+        value
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid header bit or field name '{}'", value))
+    }
+
+    /// Value of one of the little-endian 32 bit header fields
+    pub fn field_u32(header: &[bool], name: &str) -> u32 {
+        let field = find_field(name).expect("Unknown header field");
+        let start = field.start as usize;
+        let bytes = bits_to_bytes(&header[start..start + 32]);
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    /// Packs bits, most significant first, into bytes. Trailing bits that do not
+    /// fill a byte are ignored.
+    pub fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+        bits.chunks_exact(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, b| (acc << 1) | *b as u8))
+            .collect()
+    }
+
+    pub fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+            .collect()
+    }
+
+    /// Bits of each word, most significant first
+    pub fn words_to_bits(words: &[u32]) -> Vec<bool> {
+        words
+            .iter()
+            .flat_map(|word| (0..32).rev().map(move |i| (word >> i) & 1 == 1))
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn range(from: u16, to: u16) -> Vec<u16> {
+            (from..=to).collect()
+        }
+
+        #[test]
+        fn masks_parse_indexes_ranges_and_fields() {
+            assert_eq!(parse_bit_mask("336").unwrap(), vec![336]);
+            assert_eq!(parse_bit_mask("544-547").unwrap(), range(544, 547));
+            assert_eq!(parse_bit_mask("7-7").unwrap(), vec![7]);
+            assert_eq!(parse_bit_mask("time").unwrap(), range(544, 575));
+            assert_eq!(parse_bit_mask("midstate").unwrap(), range(608, 863));
+            assert_eq!(parse_bit_mask("schedule").unwrap(), range(864, 927));
+            // Bytes in serialization order, bit 0 the least significant one
+            assert_eq!(parse_bit_mask("time[0]").unwrap(), range(544, 551));
+            assert_eq!(parse_bit_mask("merkle_root[0-1]").unwrap(), range(288, 303));
+            assert_eq!(parse_bit_mask("time[0].0").unwrap(), vec![551]);
+            assert_eq!(parse_bit_mask("time[0].7").unwrap(), vec![544]);
+            assert_eq!(parse_bit_mask("bits[1-2].0").unwrap(), vec![591, 599]);
+            // Spaces and empty items are fine
+            assert_eq!(parse_bit_mask(" 3 , ,1 ").unwrap(), vec![1, 3]);
+            assert_eq!(parse_bit_mask("").unwrap(), Vec::<u16>::new());
+        }
+
+        #[test]
+        fn overlapping_masks_are_sorted_without_duplicates() {
+            assert_eq!(parse_bit_mask("5,3,4,3").unwrap(), vec![3, 4, 5]);
+            assert_eq!(
+                parse_bit_mask("time[1],544-549,time[0].0,550").unwrap(),
+                range(544, 559)
+            );
+            assert_eq!(parse_bit_mask("version,0-40").unwrap(), range(0, 40));
+        }
+
+        #[test]
+        fn invalid_masks_are_rejected() {
+            for (spec, error) in [
+                ("10-5", "Empty bit range 10-5"),
+                ("abc", "Invalid header bit or field name 'abc'"),
+                ("65536", "Invalid header bit or field name '65536'"),
+                ("1-x", "Invalid header bit or field name 'x'"),
+                ("nonce[0]", "Unknown header field 'nonce'"),
+                ("time[4]", "time[4].0 is outside the field (4 bytes)"),
+                ("time[2-4]", "time[4].0 is outside the field (4 bytes)"),
+                ("time[0].8", "time[0].8 is outside the field (4 bytes)"),
+                (
+                    "schedule[8]",
+                    "schedule[8].0 is outside the field (8 bytes)",
+                ),
+                ("time[0", "Invalid selector 'time[0'"),
+                ("time[x]", "Invalid selector 'time[x]'"),
+                ("time[0]7", "Invalid selector 'time[0]7'"),
+            ] {
+                assert_eq!(parse_bit_mask(spec).unwrap_err(), error, "{}", spec);
+            }
+        }
+
+        #[test]
+        fn bits_are_located_in_header_and_derived_fields() {
+            for (index, location) in [
+                (0, "version[0].7"),
+                (31, "version[3].0"),
+                (32, "prev_hash[0].7"),
+                (543, "merkle_root[31].0"),
+                (551, "time[0].0"),
+                (607, "bits[3].0"),
+                (608, "midstate[0].7"),
+                (863, "midstate[31].0"),
+                (864, "schedule[0].7"),
+                (927, "schedule[7].0"),
+            ] {
+                assert_eq!(locate_bit(index).unwrap().to_string(), location);
+            }
+            assert!(locate_bit(928).is_none());
+
+            // bit_index is the inverse of locate_bit
+            for index in 0..928 {
+                let location = locate_bit(index).unwrap();
+                let field = find_field(location.field).unwrap();
+                assert_eq!(bit_index(field, location.byte, location.bit), Ok(index));
+            }
+        }
+    }
+}
@@ -1,160 +1,676 @@
+#![allow(clippy::module_inception)]
+
 mod arguments;
 mod data_structures;
+mod dataset;
+mod errors;
+mod features;
 mod file;
+mod generator;
+mod header;
+mod shutdown;
 mod statistics;
 
 use std::env;
-use std::sync::mpsc::{Receiver, Sender};
 
-use crate::arguments::arguments::parse_config;
-use crate::data_structures::data_structures::{BlockHeaderData, DataAddress, Message, Statistic};
-use crate::file::file_import::{import_headers_file, import_nonce_stats_file};
-use crate::statistics::computation::account_address;
-use crate::statistics::threading::create_thread_pool;
+use crate::arguments::arguments::{
+    parse_config, parse_convert_config, parse_generate_config, parse_simulate_config, Config,
+    ConvertConfig, GenerateConfig,
+};
+use crate::data_structures::data_structures::{
+    BlockHeaderData, Checkpoint, DataAddress, InputFormat, PowValidation, RecordFormat, Scoring,
+    TopK,
+};
+use crate::dataset::dataset::{open_dataset, Dataset, Preparation, Summary};
+use crate::errors::errors::Error;
+use crate::features::derived::FEATURES;
+use crate::features::target::{target_groups, Target};
+use crate::file::file_export::{
+    save_checkpoint_to_file, save_headers_to_file, save_nonce_stats_to_file,
+};
+use crate::file::file_import::{
+    import_checkpoint_file, import_headers_file, import_nonce_stats_file, import_tuples_file,
+};
+use crate::file::file_packed::{file_sha256, save_packed_file};
+use crate::generator::mining::{mine_chain, MiningParameters};
+use crate::generator::synthetic::generate_dataset;
+use crate::header::layout::{find_field, HEADER_BITS};
+use crate::shutdown::shutdown::install_handler;
+use crate::statistics::combinatorics::TupleSpace;
+use crate::statistics::computation::kernel_name;
+use crate::statistics::estimate::{batch_size, calibrate, format_bytes, statistic_bytes};
+use crate::statistics::progress::{format_duration, ProgressReporter};
+use crate::statistics::selection::{
+    header_bit_entropies, measure_target_entropies, select_header_bits,
+};
+use crate::statistics::threading::run_batches;
+use std::collections::HashMap;
 use std::process::exit;
-
-#[macro_use]
-extern crate lazy_static;
+use std::time::{Duration, Instant};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let (
-        tuple_size,
-        slice_size,
-        filename,
-        nonce_filename,
-        start_bit,
-        end_bit,
-        sample_threshold,
-        info_threshold,
-    ) = parse_config(&args);
-
-    let data: Vec<BlockHeaderData> = import_headers_file(filename);
-    let nonce_stats = import_nonce_stats_file(nonce_filename);
-
-    // Vector to store the address as we go deeper in the recursion
-    let mut stack: Vec<u16> = Vec::new();
-    // Vector for storing the statistic of each address
-    let mut stats: Vec<Statistic> = Vec::new();
-
-    // Create the thread pool
-    //let threads = 6;
-    let threads = 14;
-
-    let (th_handles, th_senders, main_rx) = create_thread_pool(
-        &data,
-        threads,
-        &nonce_stats,
-        &sample_threshold,
-        &info_threshold,
+    // Without a command the arguments are the ones of analyze
+    let result = match args.get(1).map(|a| a.as_str()) {
+        Some("generate") => parse_generate_config(&args[1..]).and_then(generate),
+        Some("simulate") => parse_simulate_config(&args[1..])
+            .and_then(|(output_prefix, params)| simulate(&output_prefix, &params)),
+        Some("convert") => parse_convert_config(&args[1..]).and_then(convert),
+        Some("analyze") => parse_config(&args[1..]).and_then(analyze),
+        _ => parse_config(&args).and_then(analyze),
+    };
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(e.exit_code());
+    }
+}
+
+/// Writes a synthetic dataset, in every input format, together with the files
+/// needed to analyze it and check what was planted
+fn generate(config: GenerateConfig) -> Result<(), Error> {
+    let data = generate_dataset(
+        config.rows,
+        config.header_bits,
+        config.nonce_bits,
+        &config.planted,
+        config.seed,
+    )
+    .map_err(Error::Usage)?;
+
+    let planted_file = format!("{}_planted.json", config.output_prefix);
+    save_dataset(&config.output_prefix, &data)?;
+    let lines: Vec<String> = config
+        .planted
+        .iter()
+        .map(|p| json::stringify(p.clone()))
+        .collect();
+    std::fs::write(&planted_file, lines.join("\n") + "\n")
+        .map_err(|e| Error::Export(planted_file.clone(), e))?;
+    println!(
+        "Generated {} rows with {} planted dependencies into {}*",
+        config.rows,
+        config.planted.len(),
+        config.output_prefix
     );
+    Ok(())
+}
 
-    // End bit is the lowest between input size and provided max bit
-    let mut final_bit = data[0].header.len() as u16;
-    if end_bit < final_bit {
-        final_bit = end_bit;
-    }
-
-    // Recursive processing of all possibilities
-    // check all possible tuples of tuple_size in the header
-    // against each bit in the nonce. Extracts statistical data
-    // on each tuple and stores in the stats vector
-    unfold(
-        start_bit,
-        final_bit,
-        data[0].header.len() as u16,
-        data[0].nonce.len() as u8,
-        tuple_size,
-        &mut stack,
-        &mut stats,
-        &main_rx,
-        &slice_size,
+/// Mines a chain with the given nonce search strategy and writes the blocks
+/// like `generate` does
+fn simulate(output_prefix: &str, params: &MiningParameters) -> Result<(), Error> {
+    let data = mine_chain(params).map_err(Error::Data)?;
+    save_dataset(output_prefix, &data)?;
+    println!(
+        "Mined {} blocks with strategy {:?} into {}*",
+        data.len(),
+        params.strategy,
+        output_prefix
     );
+    Ok(())
+}
 
-    println!("Unfold finished!");
+/// Writes the headers in every input format and the nonce probabilities
+fn save_dataset(output_prefix: &str, data: &[BlockHeaderData]) -> Result<(), Error> {
+    let bin_file = format!("{}_bin.dat", output_prefix);
+    let hex_file = format!("{}.dat", output_prefix);
+    let nonce_file = format!("{}_nonce_probabilities.dat", output_prefix);
 
-    for thread in th_senders {
-        thread.send(Message::Stop).expect("Error sending message");
+    save_headers_to_file(&bin_file, data, InputFormat::Bin)
+        .map_err(|e| Error::Export(bin_file.clone(), e))?;
+    // Hex only works when every field is made of whole digits
+    let (header_len, nonce_len) = match data.first() {
+        Some(entry) => (entry.header.len(), entry.nonce.len()),
+        None => (0, 0),
+    };
+    if header_len.is_multiple_of(4) && nonce_len.is_multiple_of(4) {
+        save_headers_to_file(&hex_file, data, InputFormat::Hex)
+            .map_err(|e| Error::Export(hex_file.clone(), e))?;
+    } else {
+        println!("Not writing {}, bit widths are not multiple of 4", hex_file);
     }
+    save_nonce_stats_to_file(&nonce_file, data).map_err(|e| Error::Export(nonce_file.clone(), e))
+}
+
+/// Packs a hashes file into a file that analyze maps instead of parsing
+fn convert(config: ConvertConfig) -> Result<(), Error> {
+    let start = Instant::now();
+    let data = import_headers_file(config.input.clone(), config.input_format)?;
+    let sha256 = file_sha256(&config.input)?;
+    let bytes = save_packed_file(&config.output, &data, &config.input, &sha256)?;
+    println!(
+        "Packed {} rows of {} header and {} nonce bits from {} (sha256 {}) into {}, {} in {:.2}s",
+        data.len(),
+        data[0].header.len(),
+        data[0].nonce.len(),
+        config.input,
+        sha256,
+        config.output,
+        format_bytes(bytes),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
 
-    // Wait for all threads to end
-    for handle in th_handles {
-        handle.join().unwrap();
+fn analyze(config: Config) -> Result<(), Error> {
+    // Derived features are always computed together, the ones not asked
+    // for are simply not used in the tuples
+    let mut exclude_bits = config.exclude_bits.clone();
+    if !config.features.is_empty() {
+        for feature in FEATURES
+            .iter()
+            .filter(|f| !config.features.iter().any(|c| c == *f))
+        {
+            let field = find_field(feature).expect("Derived feature without layout");
+            exclude_bits.extend(field.start..field.start + field.len);
+        }
+    }
+    let preparation = Preparation {
+        validation: config.validate_pow,
+        features: !config.features.is_empty(),
+        target: config.target.clone(),
+    };
+    let (data, summary) = open_dataset(
+        &config.filename,
+        config.input_format,
+        config.chunk_rows,
+        preparation,
+    )?;
+    if let Dataset::Stream(stream) = &data {
+        println!(
+            "Streaming {} rows from {} in chunks of {}",
+            summary.rows, config.filename, stream.chunk_rows
+        );
     }
 
-    exit(0);
-}
+    if let Some(validation) = config.validate_pow {
+        let invalid = &summary.invalid_rows;
+        let lines: Vec<String> = invalid.iter().map(|n| (n + 1).to_string()).collect();
+        println!(
+            "{} of {} rows fail proof of work validation{}{}",
+            invalid.len(),
+            summary.rows_read,
+            if invalid.is_empty() { "" } else { ", lines: " },
+            lines.join(", ")
+        );
+        if validation == PowValidation::Drop && !invalid.is_empty() {
+            println!("Dropped {} rows, {} left", invalid.len(), summary.rows);
+        }
+    }
+    if summary.rows == 0 {
+        return Err(Error::Data("No rows left to analyze".to_string()));
+    }
+    // Derived features are only added to full block headers
+    let located_bits = if !config.features.is_empty() {
+        summary.header_len as u16
+    } else if summary.header_len == HEADER_BITS as usize {
+        HEADER_BITS
+    } else {
+        0
+    };
+    let format = RecordFormat {
+        located_bits,
+        nonce_target: matches!(config.target, Target::Nonce),
+    };
 
-fn unfold(
-    curr: u16,
-    max: u16,
-    s: u16,
-    nonce_len: u8,
-    n: u16,
-    stack: &mut Vec<u16>,
-    stats: &mut Vec<Statistic>,
-    main_rx: &Receiver<Message>,
-    slice_size: &usize,
-) {
-    if stats.len() > *slice_size {
-        //println!("[Main] Waiting for worker to be free...");
-        let worker = main_rx.recv().unwrap();
-        //println!("[Main] Worker called! Using it.");
-        match worker {
-            Message::Free(channel) => {
-                send_data_to_worker(stats, channel);
-            }
-            _ => {}
+    let target_len = summary.target_len as u16;
+    let target_groups = target_groups(
+        target_len,
+        config.target_groups.as_deref(),
+        config.target_group_size,
+    )
+    .map_err(Error::Usage)?;
+
+    // Explicit tuples replace the enumeration
+    let tuples: Option<Vec<DataAddress>> = config
+        .tuples_filename
+        .clone()
+        .map(import_tuples_file)
+        .transpose()?;
+    let header_len = summary.header_len;
+    for tuple in tuples.iter().flatten() {
+        if tuple.header_bits.iter().any(|b| *b as usize >= header_len)
+            || tuple.target_bits.iter().any(|b| *b >= target_len)
+        {
+            return Err(Error::Data(format!(
+                "Tuple {:?} is out of the data range",
+                tuple
+            )));
         }
     }
 
-    return match n {
-        0 => {
-            for n in 0..nonce_len {
-                account_address(stack, n, stats);
-            }
+    // Single nonce bits use the probabilities from the nonce file, anything
+    // else is measured on the data
+    let nonce_entropies = match config.target {
+        Target::Nonce => import_nonce_stats_file(config.nonce_filename.clone())?,
+        _ => HashMap::new(),
+    };
+    let mut target_entropies: HashMap<Vec<u16>, f32> = HashMap::new();
+    let mut measured: Vec<Vec<u16>> = vec![];
+    let tuple_groups = tuples.iter().flatten().map(|t| &t.target_bits);
+    for group in target_groups.iter().chain(tuple_groups) {
+        if target_entropies.contains_key(group) || measured.contains(group) {
+            continue;
         }
-        _ => {
-            for p in curr..s {
-                // limit the first number range
-                if stack.len() == 0 && p > max {
-                    println!("Finished Fold on bit {}", &p);
-                    return;
-                }
-
-                stack.push(p);
-                unfold(
-                    p + 1,
-                    max,
-                    s,
-                    nonce_len,
-                    n - 1,
-                    stack,
-                    stats,
-                    main_rx,
-                    slice_size,
-                );
-                stack.pop();
+        match nonce_entropies.get(&(group[0] as usize)) {
+            Some(entropy) if group.len() == 1 => {
+                target_entropies.insert(group.clone(), *entropy);
             }
+            _ => measured.push(group.clone()),
         }
+    }
+    let entropies = measure_target_entropies(&data, &measured, config.mode)?;
+    target_entropies.extend(measured.into_iter().zip(entropies));
+    let scoring = Scoring {
+        mode: config.mode,
+        kernel: config.kernel,
+        target_entropies,
+        sample_threshold: config.sample_threshold,
+        info_threshold: config.info_threshold,
+        max_p_value: config.max_p_value,
+        top: match (config.top_k, config.top_k_per_target) {
+            (None, None) => None,
+            (global, per_target) => Some(TopK {
+                by: config.top_by,
+                global,
+                per_target,
+            }),
+        },
     };
-}
+    println!("Counting kernel: {}", kernel_name(scoring.kernel));
+    if let Some(top) = &scoring.top {
+        println!(
+            "Keeping the best statistics by {}: {} over all, {} per group of target bits",
+            top.by.name(),
+            top.global.map_or("none".to_string(), |k| k.to_string()),
+            top.per_target.map_or("none".to_string(), |k| k.to_string())
+        );
+    }
+
+    // Leave out the header bits that carry (almost) no information, they
+    // only multiply the number of tuples to check
+    let entropies = header_bit_entropies(&summary.header_ones, summary.rows);
+    let (header_bits, excluded_bits) = select_header_bits(
+        &entropies,
+        config.start_bit,
+        config.min_bit_entropy,
+        config.include_bits.as_deref(),
+        &exclude_bits,
+    );
+    let excluded_names: Vec<String> = excluded_bits
+        .iter()
+        .map(|b| format.describe_bit(*b))
+        .collect();
+    println!(
+        "Excluded {} header bits with entropy <= {}: {}",
+        excluded_bits.len(),
+        config.min_bit_entropy,
+        excluded_names.join(", ")
+    );
+
+    // Every combination of the candidate header bits, or only the given tuples
+    let space = match tuples {
+        Some(tuples) => TupleSpace::List(tuples),
+        None => TupleSpace::Combinations {
+            header_bits,
+            max: config.end_bit,
+            tuple_size: config.tuple_size,
+            target_groups,
+        },
+    };
+    let tuples_total = space.count();
 
-fn send_data_to_worker(stats: &mut Vec<Statistic>, channel: Sender<Message>) {
-    let result = channel.send(Message::Process(stats.clone()));
-    match result {
-        Ok(_) => {
-            stats.resize(
-                0,
-                Statistic {
-                    address: DataAddress {
-                        header_bits: vec![],
-                        nonce_bit: 0,
-                    },
-                    instances: Default::default(),
-                },
+    let resume = config
+        .resume_filename
+        .clone()
+        .map(import_checkpoint_file)
+        .transpose()?;
+    let start = match &resume {
+        Some(checkpoint) => {
+            let start = resume_rank(&config, &space, checkpoint)?;
+            println!(
+                "Resuming after {} tuples, last one {:?}",
+                checkpoint.tuples_done, checkpoint.last_tuple
             );
+            start
+        }
+        None => 0,
+    };
+
+    if let Err(e) = install_handler() {
+        println!("{}", e);
+    }
+
+    let per_tuple = space.per_tuple();
+    let total = tuples_total.saturating_mul(per_tuple);
+    let done_before = start.saturating_mul(per_tuple);
+
+    if config.dry_run {
+        // Every address costs about the same, the next ones will do
+        let mut sample: Vec<DataAddress> = vec![];
+        let sample_tuples = CALIBRATION_ADDRESSES as u64 / per_tuple.max(1) + 1;
+        let sample_ranks = start..(start + sample_tuples).min(tuples_total);
+        space.for_each_address(sample_ranks, |header_bits, target_bits| {
+            sample.push(DataAddress {
+                header_bits: header_bits.to_vec(),
+                target_bits: target_bits.to_vec(),
+            })
+        });
+        sample.truncate(CALIBRATION_ADDRESSES);
+        let work = Work {
+            addresses: total.saturating_sub(done_before),
+            per_tuple,
+            workers: config.threads,
+        };
+        return dry_run(&config, &data, &summary, &scoring, &sample, &work);
+    }
+    let reporter = ProgressReporter::new(
+        total,
+        done_before,
+        config.threads,
+        Duration::from_secs(config.progress_interval),
+        config.progress_filename.clone(),
+    )?;
+
+    // The workers build the addresses of the tuples they are handed, batches
+    // only hold ranks
+    let batch_tuples = batch_size(config.slice_size, per_tuple) / per_tuple.max(1);
+    let summary = run_batches(
+        &data,
+        &space,
+        &scoring,
+        config.threads,
+        start..tuples_total,
+        batch_tuples,
+        reporter,
+        &format,
+    )?;
+    let next = start + summary.tuples;
+    let result = if next < tuples_total {
+        Err(Error::Interrupted)
+    } else {
+        println!("Enumerated {} header tuples", tuples_total);
+        Ok(())
+    };
+    println!(
+        "Run summary: {} header tuples, {} addresses enumerated, {} scored in {} batches",
+        summary.tuples, summary.enumerated, summary.scored, summary.batches
+    );
+
+    if let Err(Error::Interrupted) = result {
+        save_checkpoint_to_file(
+            &config.checkpoint_filename,
+            checkpoint_after(&config, &space, next),
+        )?;
+        println!(
+            "Stopped after {} tuples, resume with --resume {}",
+            next, config.checkpoint_filename
+        );
+    }
+    result
+}
+
+/// The checkpoint of a run stopped once its first `tuples_done` tuples are
+/// scored
+fn checkpoint_after(config: &Config, space: &TupleSpace, tuples_done: u64) -> Checkpoint {
+    Checkpoint {
+        hashes_file: config.filename.clone(),
+        tuple_size: config.tuple_size,
+        tuples_file: config.tuples_filename.clone(),
+        tuples_done,
+        last_tuple: match tuples_done {
+            0 => vec![],
+            _ => space.tuple(tuples_done - 1),
+        },
+    }
+}
+
+/// Rank of the first tuple left by the checkpoint, which has to come from the
+/// same run, numbering the tuples the same way
+fn resume_rank(config: &Config, space: &TupleSpace, checkpoint: &Checkpoint) -> Result<u64, Error> {
+    if checkpoint.hashes_file != config.filename
+        || checkpoint.tuple_size != config.tuple_size
+        || checkpoint.tuples_file != config.tuples_filename
+        || checkpoint.tuples_done > space.count()
+        || (checkpoint.tuples_done > 0
+            && space.tuple(checkpoint.tuples_done - 1) != checkpoint.last_tuple)
+    {
+        return Err(Error::Usage(format!(
+            "The checkpoint {} is not from a run with the same files, tuple size and header bits",
+            config.resume_filename.clone().unwrap_or_default()
+        )));
+    }
+    Ok(checkpoint.tuples_done)
+}
+
+// Addresses scored by the calibration of a dry run, over and over for a second
+const CALIBRATION_ADDRESSES: usize = 256;
+
+/// What a run has to do
+struct Work {
+    addresses: u64,
+    // Addresses added by each header tuple
+    per_tuple: u64,
+    workers: usize,
+}
+
+/// Prints the work a run would do, the memory it needs and how long it would
+/// take, from scoring a sample of its addresses on this machine
+fn dry_run(
+    config: &Config,
+    data: &Dataset,
+    summary: &Summary,
+    scoring: &Scoring,
+    sample: &[DataAddress],
+    work: &Work,
+) -> Result<(), Error> {
+    let batch = batch_size(config.slice_size, work.per_tuple);
+    let header_len = sample
+        .iter()
+        .map(|a| a.header_bits.len())
+        .max()
+        .unwrap_or(0);
+    let target_len = sample
+        .iter()
+        .map(|a| a.target_bits.len())
+        .max()
+        .unwrap_or(0);
+    let bytes = statistic_bytes(header_len, target_len, config.mode);
+    // The workers share the data in memory, a streamed chunk is each worker's
+    // own, a byte per bit
+    let row_bytes = (std::mem::size_of::<BlockHeaderData>()
+        + summary.header_len
+        + summary.nonce_len
+        + summary.target_len) as u64;
+
+    println!("Dry run, nothing is scored");
+    println!(
+        "Addresses: {} ({} per header tuple)",
+        work.addresses, work.per_tuple
+    );
+    println!(
+        "Batches: {} of up to {} addresses",
+        work.addresses.div_ceil(batch),
+        batch
+    );
+    println!(
+        "Memory per batch: {} ({} bytes per statistic of {} header and {} target bits)",
+        format_bytes(batch.saturating_mul(bytes)),
+        bytes,
+        header_len,
+        target_len
+    );
+    match data {
+        Dataset::Memory(_) => println!(
+            "Data: {} rows, {} shared by the {} workers",
+            summary.rows,
+            format_bytes(row_bytes * summary.rows),
+            work.workers
+        ),
+        Dataset::Stream(stream) => println!(
+            "Data: {} rows streamed in chunks of {}, {} per chunk in each of the {} workers",
+            summary.rows,
+            stream.chunk_rows,
+            format_bytes(row_bytes * stream.chunk_rows as u64),
+            work.workers
+        ),
+        Dataset::Packed(packed) => println!(
+            "Data: {} rows mapped from a packed file, {} shared by the {} workers",
+            summary.rows,
+            format_bytes(
+                ((summary.header_len + summary.nonce_len) * packed.file.header.column_words() * 8)
+                    as u64
+            ),
+            work.workers
+        ),
+    }
+
+    let secs_per_address = calibrate(data, sample, scoring, Duration::from_secs(1))?;
+    // Workers beyond the cores of the machine only share them
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let parallel = work.workers.min(cores).max(1);
+    let runtime = work.addresses as f64 * secs_per_address / parallel as f64;
+    println!(
+        "Calibration: {:.3} ms per address over {} sample addresses",
+        secs_per_address * 1000.0,
+        sample.len()
+    );
+    println!(
+        "Estimated runtime with {} workers on {} cores: {}",
+        work.workers,
+        cores,
+        format_duration(runtime)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_structures::data_structures::{CountingKernel, ScoringMode, Statistic};
+    use crate::statistics::computation::{account_address, score_statistics};
+    use std::ops::Range;
+
+    fn run_config(tuple_size: &str) -> Config {
+        let args: Vec<String> = [
+            "analyze",
+            tuple_size,
+            "1000",
+            "hashes.dat",
+            "probs.dat",
+            "0",
+            "15",
+            "0",
+            "-100",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        parse_config(&args).unwrap()
+    }
+
+    fn tuple_space(tuple_size: u16) -> TupleSpace {
+        TupleSpace::Combinations {
+            header_bits: (0..16).collect(),
+            max: 15,
+            tuple_size,
+            target_groups: vec![vec![0], vec![1, 2], vec![3]],
+        }
+    }
+
+    /// Scores the tuples of the ranks in batches, the way a run does, and
+    /// returns the statistics written
+    fn run(data: &Dataset, space: &TupleSpace, ranks: Range<u64>, batch: u64) -> Vec<String> {
+        let scoring = Scoring {
+            mode: ScoringMode::Table,
+            kernel: CountingKernel::Auto,
+            target_entropies: vec![vec![0], vec![1, 2], vec![3]]
+                .into_iter()
+                .map(|group| (group, 1.0))
+                .collect(),
+            sample_threshold: 0,
+            info_threshold: -100.0,
+            max_p_value: 1.0,
+            top: None,
+        };
+        let mut written = vec![];
+        let end = ranks.end;
+        for first in ranks.step_by(batch as usize) {
+            let mut stats: Vec<Statistic> = vec![];
+            space.for_each_address(first..(first + batch).min(end), |header, target| {
+                account_address(header, target, ScoringMode::Table, &mut stats)
+            });
+            for s in score_statistics(data, stats, &scoring).unwrap() {
+                written.push(format!("{:?}", s));
+            }
+        }
+        written
+    }
+
+    #[test]
+    fn resumed_runs_score_the_tuples_left() {
+        let mut rows = generate_dataset(300, 16, 4, &[], 9).unwrap();
+        for row in rows.iter_mut() {
+            row.target = row.nonce.clone();
+        }
+        let data = Dataset::Memory(rows);
+        let config = run_config("2");
+        let space = tuple_space(2);
+        let total = space.count();
+        let uninterrupted = run(&data, &space, 0..total, 7);
+        assert_eq!(uninterrupted.len() as u64, total * space.per_tuple());
+
+        // Stopped after 3 batches, then resumed from the checkpoint file
+        let path = std::env::temp_dir().join(format!("bt-checkpoint-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut written = run(&data, &space, 0..21, 7);
+        save_checkpoint_to_file(&path, checkpoint_after(&config, &space, 21)).unwrap();
+        let checkpoint = import_checkpoint_file(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.hashes_file, "hashes.dat");
+        assert_eq!(checkpoint.tuple_size, 2);
+        assert_eq!(checkpoint.tuples_file, None);
+        assert_eq!(checkpoint.tuples_done, 21);
+        assert_eq!(checkpoint.last_tuple, vec![1, 7]);
+        let start = resume_rank(&config, &space, &checkpoint).unwrap();
+        assert_eq!(start, 21);
+        let resumed = run(&data, &space, start..total, 7);
+        // No batch is scored twice, none is missing
+        assert_eq!(resumed[0], uninterrupted[21 * 3]);
+        written.extend(resumed);
+        assert_eq!(written, uninterrupted);
+
+        // Nothing done yet, or everything
+        for done in [0, total] {
+            let checkpoint = checkpoint_after(&config, &space, done);
+            assert_eq!(resume_rank(&config, &space, &checkpoint).unwrap(), done);
+        }
+    }
+
+    #[test]
+    fn checkpoints_of_other_runs_are_rejected() {
+        let config = run_config("2");
+        let space = tuple_space(2);
+        let checkpoint = checkpoint_after(&config, &space, 21);
+        let mut others = vec![checkpoint_after(&run_config("3"), &tuple_space(3), 21)];
+        let mut other = checkpoint.clone();
+        other.hashes_file = "other.dat".to_string();
+        others.push(other);
+        let mut other = checkpoint.clone();
+        other.tuples_file = Some("tuples.json".to_string());
+        others.push(other);
+        let mut other = checkpoint.clone();
+        other.last_tuple = vec![1, 6];
+        others.push(other);
+        let mut other = checkpoint;
+        other.tuples_done = space.count() + 1;
+        others.push(other);
+        for other in others {
+            match resume_rank(&config, &space, &other) {
+                Err(e @ Error::Usage(_)) => assert_eq!(e.exit_code(), 2),
+                result => panic!("{:?}: {:?}", other, result),
+            }
         }
-        _ => {}
     }
 }
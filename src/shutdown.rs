@@ -0,0 +1,24 @@
+pub mod shutdown {
+    //! SIGINT and SIGTERM ask the analysis to stop: no more work is handed out,
+    //! the work already handed out is finished and saved, and a checkpoint is
+    //! written to resume from. A second signal exits right away.
+    use std::process::exit;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    pub fn install_handler() -> Result<(), String> {
+        ctrlc::set_handler(|| {
+            if STOP_REQUESTED.swap(true, Ordering::SeqCst) {
+                println!("Stopping right away, the results of running batches are lost");
+                exit(130);
+            }
+            println!("Stopping after the running batches, signal again to stop right away");
+        })
+        .map_err(|e| format!("Cannot install the signal handler: {}", e))
+    }
+
+    pub fn stop_requested() -> bool {
+        STOP_REQUESTED.load(Ordering::SeqCst)
+    }
+}
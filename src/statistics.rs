@@ -1,155 +1,671 @@
 pub mod threading {
-    use crate::data_structures::data_structures::{BlockHeaderData, Message};
-    use crate::statistics::computation::compute_histogram_and_stats;
-    use std::collections::HashMap;
+    //! The workers are a work-stealing pool: each one takes the next batch of
+    //! tuple ranks as soon as it is free, builds the addresses of the batch and
+    //! scores them. The scored batches go over a channel to the main thread,
+    //! the output sink, which saves them and reports progress.
+    use crate::data_structures::data_structures::{
+        BatchReport, BatchResult, RecordFormat, Scoring,
+    };
+    use crate::dataset::dataset::Dataset;
+    use crate::errors::errors::Error;
+    use crate::file::file_export::{save_stats_to_file, save_top_stats_to_file};
+    use crate::shutdown::shutdown::stop_requested;
+    use crate::statistics::combinatorics::TupleSpace;
+    use crate::statistics::computation::{account_address, score_statistics};
+    use crate::statistics::progress::ProgressReporter;
+    use crate::statistics::ranking::TopResults;
+    use rayon::iter::{ParallelBridge, ParallelIterator};
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
     use std::sync::mpsc;
-    use std::sync::mpsc::{Receiver, Sender};
-    use std::thread::JoinHandle;
     use std::time::Instant;
 
-    pub fn create_thread_pool(
-        data: &Vec<BlockHeaderData>,
-        threads: i32,
-        nonce_stats: &HashMap<usize, f32>,
-        samples_threshold: &usize,
-        info_threshold: &f32,
-    ) -> (Vec<JoinHandle<()>>, Vec<Sender<Message>>, Receiver<Message>) {
-        let mut th_handles = vec![];
-        let mut th_senders = vec![];
-        let (main_tx, main_rx) = mpsc::channel();
-
-        for _ in 0..(threads - 1) {
-            let (tx, rx) = mpsc::channel();
-            let data_clone = data.clone();
-            let main_transmitter = main_tx.clone();
-            let my_transmitter = tx.clone();
-            let nonce_stats_clone = nonce_stats.clone();
-            let info_thr_clone = info_threshold.clone();
-            let sample_thr_clone = samples_threshold.clone();
-
-            th_senders.push(tx.clone());
-            th_handles.push(std::thread::spawn(move || {
-                worker(
-                    data_clone,
-                    rx,
-                    my_transmitter,
-                    main_transmitter,
-                    nonce_stats_clone,
-                    info_thr_clone,
-                    sample_thr_clone,
-                )
-            }));
-            main_tx
-                .send(Message::Free(tx.clone()))
-                .expect("Error sending message");
-        }
-        (th_handles, th_senders, main_rx)
-    }
-
-    pub fn worker(
-        data: Vec<BlockHeaderData>,
-        rx: Receiver<Message>,
-        tx: Sender<Message>,
-        main_tx: Sender<Message>,
-        nonce_stats: HashMap<usize, f32>,
-        info_threshold: f32,
-        sample_threshold: usize,
-    ) {
-        for received in rx {
-            match received {
-                Message::Process(vector) => {
-                    let length = vector.len();
-                    println!("[worker] Received {} entries.", length);
-                    let now = Instant::now();
-                    compute_histogram_and_stats(
-                        &data,
-                        vector,
-                        &nonce_stats,
-                        &info_threshold,
-                        &sample_threshold,
-                    );
-                    let secs = now.elapsed().as_secs();
-                    let rate = (length as f32) / (secs as f32);
-                    println!(
-                        "[worker] Finished working, sending message for more work. Took {} secs, rate: {} items/s",
-                        secs, rate
-                    );
-                    main_tx
-                        .send(Message::Free(tx.clone()))
-                        .expect("Error sending message");
+    /// Counts of a finished run
+    #[derive(Debug, Clone)]
+    pub struct RunSummary {
+        // Header tuples scored, from the first rank of the run on
+        pub tuples: u64,
+        // Addresses handed to the workers
+        pub enumerated: u64,
+        // Acknowledged by the output sink
+        pub scored: u64,
+        pub batches: u64,
+    }
+
+    /// Scores the header tuples of the given ranks in batches of
+    /// `batch_tuples`, on `workers` threads. Once a stop is requested no batch
+    /// is started, the ones running are finished and saved: the tuples scored
+    /// are always the first ones of `ranks`, a checkpoint can resume after them.
+    /// The statistics are written as records of the given format.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run_batches(
+        data: &Dataset,
+        space: &TupleSpace,
+        scoring: &Scoring,
+        workers: usize,
+        ranks: Range<u64>,
+        batch_tuples: u64,
+        mut reporter: ProgressReporter,
+        format: &RecordFormat,
+    ) -> Result<RunSummary, Error> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|id| format!("worker-{}", id))
+            .build()
+            .map_err(|e| Error::Worker(e.to_string()))?;
+        let per_tuple = space.per_tuple();
+        let enumerated = AtomicU64::new(0);
+        // The sink failed, no need to score any more
+        let abort = AtomicBool::new(false);
+        let end = ranks.end;
+        let batches = ranks
+            .step_by(batch_tuples.max(1) as usize)
+            .map(|first| first..(first + batch_tuples.max(1)).min(end))
+            .take_while(|_| !stop_requested() && !abort.load(Ordering::SeqCst))
+            .inspect(|batch| {
+                enumerated.fetch_add((batch.end - batch.start) * per_tuple, Ordering::SeqCst);
+            });
+
+        let mut summary = RunSummary {
+            tuples: 0,
+            enumerated: 0,
+            scored: 0,
+            batches: 0,
+        };
+        let (tx, rx) = mpsc::channel();
+        let (scoring_result, sink_result) = std::thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                pool.install(|| {
+                    batches.par_bridge().try_for_each_with(tx, |tx, batch| {
+                        score_batch(data, space, scoring, batch, tx)
+                    })
+                })
+            });
+            let sink_result = (|| {
+                let mut best = scoring.top.as_ref().map(TopResults::new);
+                for result in &rx {
+                    summary.tuples += result.tuples;
+                    summary.scored += result.report.scored as u64;
+                    summary.batches += 1;
+                    reporter.record(&result.report)?;
+                    // If something is there, dump it
+                    if !result.stats.is_empty() {
+                        save_stats_to_file(result.stats, format)?;
+                    }
+                    if let (Some(best), Some(top)) = (&mut best, result.top) {
+                        best.merge(top);
+                    }
                 }
-                Message::Stop => {
-                    println!("[worker] Received stop message, stopping");
-                    break;
+                if let Some(best) = best {
+                    let (global, per_target) = best.into_sorted();
+                    if let Some(stats) = global {
+                        save_top_stats_to_file("top", stats, format)?;
+                    }
+                    if let Some(stats) = per_target {
+                        save_top_stats_to_file("top_per_target", stats, format)?;
+                    }
                 }
-                _ => {}
+                reporter.report()
+            })();
+            if sink_result.is_err() {
+                abort.store(true, Ordering::SeqCst);
+                // Workers still sending find the channel closed
+                drop(rx);
             }
+            let scoring_result = producer
+                .join()
+                .unwrap_or_else(|panic| Err(panic_error(panic)));
+            (scoring_result, sink_result)
+        });
+        // A failed sink also makes the workers fail, its error is the one that matters
+        sink_result?;
+        scoring_result?;
+
+        summary.enumerated = enumerated.load(Ordering::SeqCst);
+        if summary.scored != summary.enumerated {
+            return Err(Error::Worker(format!(
+                "{} addresses enumerated but {} scored",
+                summary.enumerated, summary.scored
+            )));
         }
+        Ok(summary)
+    }
+
+    /// Builds the addresses of the tuples of a batch, scores them and hands
+    /// the result to the output sink
+    fn score_batch(
+        data: &Dataset,
+        space: &TupleSpace,
+        scoring: &Scoring,
+        ranks: Range<u64>,
+        tx: &mut mpsc::Sender<BatchResult>,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut stats = vec![];
+        let tuples = ranks.end - ranks.start;
+        space.for_each_address(ranks, |header_bits, target_bits| {
+            account_address(header_bits, target_bits, scoring.mode, &mut stats)
+        });
+        let scored = stats.len();
+        let mut stats = score_statistics(data, stats, scoring)?;
+        // Only the best of the batch can be among the best of the run
+        let top = scoring.top.as_ref().map(|top| {
+            let mut best = TopResults::new(top);
+            best.add(std::mem::take(&mut stats));
+            best
+        });
+        let report = BatchReport {
+            worker: rayon::current_thread_index().unwrap_or(0),
+            scored,
+            busy: now.elapsed(),
+        };
+        tx.send(BatchResult {
+            report,
+            tuples,
+            stats,
+            top,
+        })
+        .map_err(|_| Error::Worker("the output sink stopped".to_string()))
+    }
+
+    /// Error of a worker that panicked, for `JoinHandle::join`
+    fn panic_error(panic: Box<dyn std::any::Any + Send>) -> Error {
+        let message = match panic.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => panic
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        Error::Worker(message)
     }
 }
 
 pub mod computation {
     use crate::data_structures::data_structures::{
-        BlockHeaderData, DataAddress, DataInstance, Statistic,
+        BlockHeaderData, CountingKernel, DataAddress, DataInstance, LinearBias, Scoring,
+        ScoringMode, Statistic, TopScore,
     };
-    use crate::file::file_export::save_stats_to_file;
+    use crate::dataset::dataset::{Dataset, Packed};
+    use crate::errors::errors::Error;
+    use std::borrow::Cow;
     use std::collections::HashMap;
+    use std::f64::consts::SQRT_2;
+    use std::ops::Range;
 
-    pub fn compute_histogram_and_stats(
-        data: &Vec<BlockHeaderData>,
-        stats: Vec<Statistic>,
-        nonce_stats: &HashMap<usize, f32>,
-        info_threshold: &f32,
-        sample_threshold: &usize,
-    ) {
+    // Rows packed into columns at a time, 8 KiB per column
+    const BLOCK_ROWS: usize = 1 << 16;
+
+    /// Fills in the statistics from the data and returns the ones that pass
+    /// the thresholds of the scoring
+    pub fn score_statistics(
+        data: &Dataset,
+        mut stats: Vec<Statistic>,
+        scoring: &Scoring,
+    ) -> Result<Vec<Statistic>, Error> {
+        let samples = count_statistics(data, &mut stats, scoring.mode, scoring.kernel)?;
         let mut final_stats = vec![];
-        for mut s in stats {
-            for entry in data {
-                let mut header_value: u32 = 0;
-                let h_bits: &Vec<u16> = &s.address.header_bits;
-                let test_vec: Vec<bool> = h_bits
+        for s in stats {
+            if let Some(s) = finish_statistic(s, samples, scoring)? {
+                final_stats.push(s);
+            }
+        }
+        Ok(final_stats)
+    }
+
+    /// Adds the rows of the data to the counts of the statistics, returns the
+    /// number of rows. Streamed data is counted a chunk at a time, the counts
+    /// adding up across the chunks. The columns of a packed file are counted
+    /// as they are mapped, a block of rows at a time.
+    fn count_statistics(
+        data: &Dataset,
+        stats: &mut [Statistic],
+        mode: ScoringMode,
+        kernel: CountingKernel,
+    ) -> Result<usize, Error> {
+        // The addresses of a header tuple follow each other, they are counted
+        // in the same pass over the rows
+        let same_tuple =
+            |a: &Statistic, b: &Statistic| a.address.header_bits == b.address.header_bits;
+        let popcount = Popcount::select(kernel);
+        let count_block = |columns: &Columns, stats: &mut [Statistic]| {
+            // The target values are shared by the tuples of the batch
+            let mut targets = HashMap::new();
+            for tuple in stats.chunk_by_mut(same_tuple) {
+                count_tuple_columns(columns, tuple, mode, popcount, &mut targets);
+            }
+        };
+        let mut samples = 0;
+        match data {
+            Dataset::Packed(packed) if kernel != CountingKernel::Rows => {
+                for start in (0..packed.rows()).step_by(BLOCK_ROWS) {
+                    let end = (start + BLOCK_ROWS).min(packed.rows());
+                    count_block(&Columns::map(packed, start..end, stats), stats);
+                }
+                samples = packed.rows();
+            }
+            _ => data.for_each_chunk(|rows| {
+                if kernel == CountingKernel::Rows {
+                    for tuple in stats.chunk_by_mut(same_tuple) {
+                        count_tuple(rows, tuple, mode);
+                    }
+                } else {
+                    for block in rows.chunks(BLOCK_ROWS) {
+                        count_block(&Columns::pack(block, stats), stats);
+                    }
+                }
+                samples += rows.len();
+                Ok(())
+            })?,
+        }
+        Ok(samples)
+    }
+
+    /// Adds the target values of every address of one header tuple to its
+    /// counts. The header value is computed once per row for all the addresses,
+    /// into a table of header values by the target values of each address.
+    fn count_tuple(data: &[BlockHeaderData], tuple: &mut [Statistic], mode: ScoringMode) {
+        let header_bits = tuple[0].address.header_bits.clone();
+        let value = |bits: &[bool], positions: &[u16]| match mode {
+            ScoringMode::Table => bits_value(bits, positions),
+            ScoringMode::Parity => bits_parity(bits, positions),
+        } as usize;
+        // A parity is a single bit, whatever the number of bits it covers
+        let (header_values, widths): (usize, Vec<usize>) = match mode {
+            ScoringMode::Table => (
+                1 << header_bits.len(),
+                tuple
+                    .iter()
+                    .map(|s| 1 << s.address.target_bits.len())
+                    .collect(),
+            ),
+            ScoringMode::Parity => (2, vec![2; tuple.len()]),
+        };
+        let offsets: Vec<usize> = widths
+            .iter()
+            .scan(0, |offset, width| {
+                *offset += width;
+                Some(*offset - width)
+            })
+            .collect();
+        let stride: usize = widths.iter().sum();
+
+        let mut table = vec![0u32; header_values * stride];
+        for entry in data {
+            let header_value = value(&entry.header, &header_bits);
+            let row = &mut table[header_value * stride..(header_value + 1) * stride];
+            for (s, offset) in tuple.iter().zip(&offsets) {
+                row[offset + value(&entry.target, &s.address.target_bits)] += 1;
+            }
+        }
+
+        for (s, (offset, width)) in tuple.iter_mut().zip(offsets.iter().zip(&widths)) {
+            if s.counts.is_empty() {
+                s.counts = vec![0; header_values * width];
+            }
+            for (counts, row) in s
+                .counts
+                .chunks_exact_mut(*width)
+                .zip(table.chunks_exact(stride))
+            {
+                for (count, new) in counts.iter_mut().zip(&row[*offset..offset + width]) {
+                    *count += new;
+                }
+            }
+        }
+    }
+
+    /// The bits of a block of rows used by a batch, a column of words per bit,
+    /// row r being bit r % 64 of word r / 64 as in packed files. Packed from
+    /// rows, or borrowed from the columns of a packed file.
+    struct Columns<'a> {
+        // Ones for the rows of the block, zeros past them
+        all: Vec<u64>,
+        header: HashMap<u16, Cow<'a, [u64]>>,
+        target: HashMap<u16, Cow<'a, [u64]>>,
+    }
+
+    impl<'a> Columns<'a> {
+        fn pack(rows: &[BlockHeaderData], stats: &[Statistic]) -> Columns<'a> {
+            let words = rows.len().div_ceil(64);
+            let (header_bits, target_bits) = used_bits(stats);
+            let mut header = vec![vec![0u64; words]; header_bits.len()];
+            let mut target = vec![vec![0u64; words]; target_bits.len()];
+            for (r, row) in rows.iter().enumerate() {
+                let (word, shift) = (r / 64, r % 64);
+                for (column, bit) in header.iter_mut().zip(&header_bits) {
+                    column[word] |= (row.header[*bit as usize] as u64) << shift;
+                }
+                for (column, bit) in target.iter_mut().zip(&target_bits) {
+                    column[word] |= (row.target[*bit as usize] as u64) << shift;
+                }
+            }
+            Columns {
+                all: all_rows(rows.len()),
+                header: header_bits
+                    .into_iter()
+                    .zip(header.into_iter().map(Cow::Owned))
+                    .collect(),
+                target: target_bits
                     .into_iter()
-                    .map(|x| entry.header[*x as usize])
-                    .collect();
-                for (i, val) in test_vec.iter().rev().enumerate() {
-                    header_value += (*val as u32) * 2u32.pow(i as u32);
+                    .zip(target.into_iter().map(Cow::Owned))
+                    .collect(),
+            }
+        }
+
+        /// The columns of the rows of a packed file, `rows` starting at a
+        /// multiple of 64
+        fn map(packed: &'a Packed, rows: Range<usize>, stats: &[Statistic]) -> Columns<'a> {
+            let words = rows.start / 64..rows.end.div_ceil(64);
+            let (header_bits, target_bits) = used_bits(stats);
+            let borrow = |column: &'a [u64]| Cow::Borrowed(&column[words.clone()]);
+            Columns {
+                all: all_rows(rows.len()),
+                header: header_bits
+                    .into_iter()
+                    .map(|bit| (bit, borrow(packed.header_column(bit))))
+                    .collect(),
+                target: target_bits
+                    .into_iter()
+                    .map(|bit| (bit, borrow(packed.target_column(bit))))
+                    .collect(),
+            }
+        }
+
+        /// A column per value of the bits, with the rows where the bits have
+        /// that value, in the order of `bits_value`. Only the two values of
+        /// the parity of the bits in parity mode.
+        fn values(
+            &self,
+            columns: &HashMap<u16, Cow<'a, [u64]>>,
+            bits: &[u16],
+            mode: ScoringMode,
+        ) -> Vec<Vec<u64>> {
+            match mode {
+                ScoringMode::Table => {
+                    let mut values = vec![self.all.clone()];
+                    for bit in bits {
+                        let column = columns[bit].as_ref();
+                        values = values
+                            .iter()
+                            .flat_map(|rows| {
+                                let zero = rows.iter().zip(column).map(|(r, c)| r & !c).collect();
+                                let one = rows.iter().zip(column).map(|(r, c)| r & c).collect();
+                                [zero, one]
+                            })
+                            .collect();
+                    }
+                    values
+                }
+                ScoringMode::Parity => {
+                    let mut one = vec![0u64; self.all.len()];
+                    for bit in bits {
+                        for (p, c) in one.iter_mut().zip(columns[bit].iter()) {
+                            *p ^= c;
+                        }
+                    }
+                    let zero = self.all.iter().zip(&one).map(|(r, p)| r & !p).collect();
+                    vec![zero, one]
                 }
+            }
+        }
+    }
 
-                //println!("Header bits: {:?}, test_vec {:?} => Val: {}", &s.address.header_bits, &test_vec, &header_value);
+    /// The header and target bits used by the statistics, sorted
+    fn used_bits(stats: &[Statistic]) -> (Vec<u16>, Vec<u16>) {
+        let used = |bits: fn(&Statistic) -> &[u16]| {
+            let mut used: Vec<u16> = stats.iter().flat_map(bits).copied().collect();
+            used.sort_unstable();
+            used.dedup();
+            used
+        };
+        (
+            used(|s| &s.address.header_bits),
+            used(|s| &s.address.target_bits),
+        )
+    }
 
-                let nonce_value: u32 = entry.nonce[s.address.nonce_bit as usize] as u32;
-                let data_stats: &mut DataInstance = s.instances.get_mut(&header_value).unwrap();
-                if nonce_value > 0 {
-                    data_stats.ones += 1;
-                } else {
-                    data_stats.zeros += 1;
+    /// Ones for `rows` rows, zeros past them in the last word
+    fn all_rows(rows: usize) -> Vec<u64> {
+        let mut all = vec![u64::MAX; rows.div_ceil(64)];
+        if let Some(last) = all.last_mut() {
+            if !rows.is_multiple_of(64) {
+                *last = (1 << (rows % 64)) - 1;
+            }
+        }
+        all
+    }
+
+    /// Adds the target values of every address of one header tuple to its
+    /// counts, from the columns of a block: the count of a header value and a
+    /// target value is the popcount of the AND of their columns. The last
+    /// target value gets what the others leave of the header value.
+    fn count_tuple_columns(
+        columns: &Columns<'_>,
+        tuple: &mut [Statistic],
+        mode: ScoringMode,
+        popcount: Popcount,
+        targets: &mut HashMap<Vec<u16>, Vec<Vec<u64>>>,
+    ) {
+        let header = columns.values(&columns.header, &tuple[0].address.header_bits, mode);
+        for s in tuple.iter_mut() {
+            let target = targets
+                .entry(s.address.target_bits.clone())
+                .or_insert_with(|| columns.values(&columns.target, &s.address.target_bits, mode));
+            if s.counts.is_empty() {
+                s.counts = vec![0; header.len() * target.len()];
+            }
+            for (counts, rows) in s.counts.chunks_exact_mut(target.len()).zip(&header) {
+                let mut left = (popcount.and_count)(rows, rows) as u32;
+                let last = counts.len() - 1;
+                for (count, target_rows) in counts[..last].iter_mut().zip(target.iter()) {
+                    let n = (popcount.and_count)(rows, target_rows) as u32;
+                    *count += n;
+                    left -= n;
                 }
+                counts[last] += left;
             }
-            let nonce_as_usize: usize = s.address.nonce_bit as usize;
-            let nonce_bit_entropy = nonce_stats
-                .get(&nonce_as_usize)
-                .expect("Nonce bit not found");
-            update_statistics(&mut s.instances, nonce_bit_entropy);
+        }
+    }
 
-            let mut del_keys = vec![];
-            for (key, instance) in &s.instances {
-                if !passes_thresholds(&instance, &sample_threshold, &info_threshold) {
-                    //		  s.instances.remove(&key);
-                    del_keys.push(key.clone());
+    /// Set bits of `a & b`, with the instructions chosen for the CPU
+    #[derive(Clone, Copy)]
+    struct Popcount {
+        name: &'static str,
+        and_count: fn(&[u64], &[u64]) -> u64,
+    }
+
+    impl Popcount {
+        fn select(kernel: CountingKernel) -> Popcount {
+            #[cfg(target_arch = "x86_64")]
+            {
+                if kernel == CountingKernel::Auto {
+                    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("popcnt") {
+                        return Popcount {
+                            name: "avx2",
+                            and_count: x86::and_count_avx2,
+                        };
+                    }
+                    if is_x86_feature_detected!("popcnt") {
+                        return Popcount {
+                            name: "popcnt",
+                            and_count: x86::and_count_popcnt,
+                        };
+                    }
                 }
             }
+            Popcount {
+                name: "scalar",
+                and_count: and_count_scalar,
+            }
+        }
+    }
 
-            for x in del_keys {
-                s.instances.remove(&x);
+    /// What counts the rows with `kernel` on this CPU, e.g. `avx2`
+    pub fn kernel_name(kernel: CountingKernel) -> &'static str {
+        match kernel {
+            CountingKernel::Rows => "rows",
+            kernel => Popcount::select(kernel).name,
+        }
+    }
+
+    // Inlined into the x86 kernels, which compile it with their instructions
+    #[inline(always)]
+    fn and_count_scalar(a: &[u64], b: &[u64]) -> u64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x & y).count_ones() as u64)
+            .sum()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    mod x86 {
+        use super::and_count_scalar;
+        use std::arch::x86_64::*;
+
+        /// Popcount of 256 bits at a time: each nibble looks up its count in a
+        /// 16 byte table with a shuffle, the byte counts are summed into the
+        /// four 64 bit lanes. The words past the last 4 are counted one by one.
+        #[target_feature(enable = "avx2,popcnt")]
+        unsafe fn avx2(a: &[u64], b: &[u64]) -> u64 {
+            let words = a.len().min(b.len());
+            let lookup = _mm256_setr_epi8(
+                0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3,
+                2, 3, 3, 4,
+            );
+            let low_nibbles = _mm256_set1_epi8(0x0f);
+            let mut total = _mm256_setzero_si256();
+            let vectors = words / 4;
+            for i in 0..vectors {
+                let x = _mm256_loadu_si256(a.as_ptr().add(i * 4) as *const __m256i);
+                let y = _mm256_loadu_si256(b.as_ptr().add(i * 4) as *const __m256i);
+                let v = _mm256_and_si256(x, y);
+                let low = _mm256_and_si256(v, low_nibbles);
+                let high = _mm256_and_si256(_mm256_srli_epi16::<4>(v), low_nibbles);
+                let bytes = _mm256_add_epi8(
+                    _mm256_shuffle_epi8(lookup, low),
+                    _mm256_shuffle_epi8(lookup, high),
+                );
+                total = _mm256_add_epi64(total, _mm256_sad_epu8(bytes, _mm256_setzero_si256()));
             }
+            let mut lanes = [0u64; 4];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+            let tail = vectors * 4..words;
+            lanes.iter().sum::<u64>() + and_count_scalar(&a[tail.clone()], &b[tail])
+        }
 
-            if s.instances.len() > 0 {
-                final_stats.push(s);
+        #[target_feature(enable = "popcnt")]
+        unsafe fn popcnt(a: &[u64], b: &[u64]) -> u64 {
+            and_count_scalar(a, b)
+        }
+
+        // Only selected once the CPU is known to have the instructions
+        pub fn and_count_avx2(a: &[u64], b: &[u64]) -> u64 {
+            unsafe { avx2(a, b) }
+        }
+
+        pub fn and_count_popcnt(a: &[u64], b: &[u64]) -> u64 {
+            unsafe { popcnt(a, b) }
+        }
+    }
+
+    /// Derives the probabilities and information of a counted statistic, and
+    /// keeps it if it passes the thresholds of the scoring
+    fn finish_statistic(
+        mut s: Statistic,
+        samples: usize,
+        scoring: &Scoring,
+    ) -> Result<Option<Statistic>, Error> {
+        let target_entropy = *scoring
+            .target_entropies
+            .get(&s.address.target_bits)
+            .ok_or_else(|| {
+                Error::Data(format!(
+                    "No entropy for target bits {:?}",
+                    s.address.target_bits
+                ))
+            })?;
+        let counts = std::mem::take(&mut s.counts);
+        let instances: Vec<(u32, DataInstance)> = counts
+            .chunks_exact(s.target_values)
+            .enumerate()
+            .map(|(key, counts)| (key as u32, data_instance(counts, &target_entropy)))
+            .collect();
+        s.mutual_information = mutual_information(&instances, s.target_values, samples as u32);
+
+        // In parity mode the statistic stands or falls with its bias, or is
+        // ranked by its score in top-K mode
+        if scoring.mode == ScoringMode::Parity {
+            let bias = linear_bias(&counts);
+            let p_value = bias.p_value;
+            let significant = samples > scoring.sample_threshold
+                && (scoring.top.is_some() || p_value <= scoring.max_p_value);
+            s.linear_bias = Some(bias);
+            s.instances = instances;
+            if let Some(top) = &scoring.top {
+                s.score = Some(top_score(&s, top.by, p_value));
+            }
+            return Ok(if significant { Some(s) } else { None });
+        }
+
+        let info_threshold = match scoring.top {
+            Some(_) => f32::NEG_INFINITY,
+            None => scoring.info_threshold,
+        };
+        s.instances = instances
+            .into_iter()
+            .filter(|(_, instance)| {
+                passes_thresholds(instance, &scoring.sample_threshold, &info_threshold)
+            })
+            .collect();
+        if s.instances.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(top) = &scoring.top {
+            // G-test of independence of the header tuple and the target
+            let header_values = counts.len() / s.target_values;
+            let g = g_statistic(&counts, s.target_values);
+            let dof = ((header_values - 1) * (s.target_values - 1)) as f64;
+            s.score = Some(top_score(&s, top.by, chi_square_p_value(g, dof)));
+        }
+        Ok(Some(s))
+    }
+
+    /// G statistic of the contingency table of header values (rows of
+    /// `target_values` counts) against target values, 2 sum O ln(O / E) with E
+    /// the count expected from the row and column totals
+    fn g_statistic(counts: &[u32], target_values: usize) -> f64 {
+        let mut columns = vec![0u64; target_values];
+        for row in counts.chunks_exact(target_values) {
+            for (column, count) in columns.iter_mut().zip(row) {
+                *column += *count as u64;
+            }
+        }
+        let samples: u64 = columns.iter().sum();
+        let mut g = 0.0;
+        for row in counts.chunks_exact(target_values) {
+            let row_total: u64 = row.iter().map(|count| *count as u64).sum();
+            for (column, count) in columns.iter().zip(row) {
+                if *count > 0 {
+                    let expected = row_total as f64 * *column as f64 / samples as f64;
+                    g += *count as f64 * (*count as f64 / expected).ln();
+                }
             }
         }
-        // If something is there, dump it
-        if final_stats.len() > 0 {
-            save_stats_to_file(final_stats);
+        (2.0 * g).max(0.0)
+    }
+
+    /// Score of a statistic for the top-K mode, given its p-value
+    fn top_score(s: &Statistic, by: TopScore, p_value: f64) -> f64 {
+        match by {
+            TopScore::MutualInformation => s.mutual_information as f64,
+            TopScore::PValue => p_value,
+            TopScore::Information => s
+                .instances
+                .iter()
+                .map(|(_, instance)| instance.information as f64)
+                .fold(f64::NEG_INFINITY, f64::max),
         }
     }
 
@@ -161,50 +677,1279 @@ pub mod computation {
         result
     }
 
-    pub fn account_address(stack: &Vec<u16>, nonce_bit: u8, stats: &mut Vec<Statistic>) {
-        let mut address_stat: Statistic = Statistic {
+    /// Value of the given bits read as a binary number, the first bit being the
+    /// most significant one
+    pub fn bits_value(bits: &[bool], positions: &[u16]) -> u32 {
+        positions
+            .iter()
+            .fold(0u32, |acc, p| (acc << 1) | bits[*p as usize] as u32)
+    }
+
+    /// XOR of the given bits
+    pub fn bits_parity(bits: &[bool], positions: &[u16]) -> u32 {
+        positions
+            .iter()
+            .fold(0u32, |acc, p| acc ^ bits[*p as usize] as u32)
+    }
+
+    /// Bias of the agreement between header parity (the instance key) and
+    /// target parity, with its significance from the normal approximation of
+    /// the binomial distribution. `counts` holds the zeros and ones of the
+    /// target parity for header parity 0, then for header parity 1.
+    fn linear_bias(counts: &[u32]) -> LinearBias {
+        let agree = counts[0] + counts[3];
+        let total: u32 = counts.iter().sum();
+        if total == 0 {
+            return LinearBias {
+                bias: 0.0,
+                z_score: 0.0,
+                p_value: 1.0,
+            };
+        }
+        let bias = agree as f64 / total as f64 - 0.5;
+        let z_score = 2.0 * bias * (total as f64).sqrt();
+        LinearBias {
+            bias: bias as f32,
+            z_score: z_score as f32,
+            p_value: erfc(z_score.abs() / SQRT_2),
+        }
+    }
+
+    /// Complementary error function, with a fractional error below 1.2e-7
+    /// (Numerical Recipes' Chebyshev fit), good for very small p-values too
+    pub fn erfc(x: f64) -> f64 {
+        let z = x.abs();
+        let t = 1.0 / (1.0 + 0.5 * z);
+        let poly = -z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+        let result = t * poly.exp();
+        if x >= 0.0 {
+            result
+        } else {
+            2.0 - result
+        }
+    }
+
+    /// Probability of a chi-square statistic of at least `x` with `dof` degrees
+    /// of freedom, from the Wilson-Hilferty normal approximation
+    pub fn chi_square_p_value(x: f64, dof: f64) -> f64 {
+        let h = 2.0 / (9.0 * dof);
+        let z = ((x.max(0.0) / dof).cbrt() - (1.0 - h)) / h.sqrt();
+        0.5 * erfc(z / SQRT_2)
+    }
+
+    /// Adds the statistic of an address to `stats`. Its counts are only
+    /// allocated by the worker that scores it.
+    pub fn account_address(
+        stack: &[u16],
+        target_bits: &[u16],
+        mode: ScoringMode,
+        stats: &mut Vec<Statistic>,
+    ) {
+        // A parity is a single bit, whatever the number of bits it covers
+        let target_values = match mode {
+            ScoringMode::Table => 1 << target_bits.len(),
+            ScoringMode::Parity => 2,
+        };
+        stats.push(Statistic {
             address: DataAddress {
-                header_bits: stack.to_vec().clone(),
-                nonce_bit,
+                header_bits: stack.to_vec(),
+                target_bits: target_bits.to_vec(),
             },
-            instances: HashMap::new(),
-        };
-        let max: u32 = 2u32.pow(address_stat.address.header_bits.len() as u32);
-        for k in 0..max {
-            let new_data_instance: DataInstance = DataInstance {
-                zeros: 0,
-                ones: 0,
-                total: 0,
-                p_zero: 0.0,
-                p_one: 0.0,
-                entropy: 0.0,
-                information: 0.0,
+            counts: vec![],
+            target_values,
+            instances: vec![],
+            mutual_information: 0.0,
+            linear_bias: None,
+            score: None,
+        });
+    }
+
+    /// The probabilities, entropy and information of the target for one
+    /// value of the header tuple
+    fn data_instance(counts: &[u32], target_entropy: &f32) -> DataInstance {
+        let total: u32 = counts.iter().sum();
+        let probabilities: Vec<f32> = counts
+            .iter()
+            .map(|count| {
+                if total > 0 {
+                    *count as f32 / total as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let entropy = entropy(&probabilities);
+        DataInstance {
+            counts: counts.to_vec(),
+            total,
+            probabilities,
+            entropy,
+            information: target_entropy - entropy,
+        }
+    }
+
+    /// Mutual information between the header tuple and the target, i.e. the
+    /// entropy of the target in the counted rows less its entropy given the
+    /// header tuple. The prior target entropy of the instances is not used, as
+    /// it need not match the rows.
+    fn mutual_information(
+        instances: &[(u32, DataInstance)],
+        target_values: usize,
+        samples: u32,
+    ) -> f32 {
+        if samples == 0 {
+            return 0.0;
+        }
+        let mut totals = vec![0u32; target_values];
+        let mut conditional_entropy: f32 = 0.0;
+        for (_, instance) in instances {
+            for (total, count) in totals.iter_mut().zip(&instance.counts) {
+                *total += count;
+            }
+            conditional_entropy += instance.total as f32 / samples as f32 * instance.entropy;
+        }
+        let probabilities: Vec<f32> = totals
+            .iter()
+            .map(|total| *total as f32 / samples as f32)
+            .collect();
+        // Never below zero, whatever the rounding
+        (entropy(&probabilities) - conditional_entropy).max(0.0)
+    }
+
+    pub fn entropy(probabilities: &[f32]) -> f32 {
+        let mut entropy: f32 = 0.0;
+        for p in probabilities {
+            if *p > 0.0 {
+                entropy += p * p.log2();
+            }
+        }
+        -entropy
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::data_structures::data_structures::{InputFormat, TopK};
+        use crate::dataset::dataset::{open_dataset, Preparation};
+        use crate::features::target::Target;
+        use crate::file::file_packed::save_packed_file;
+        use crate::generator::random::Rng;
+        use crate::generator::synthetic::{generate_dataset, parse_planted};
+        use std::f64::consts::LN_2;
+        use std::time::Instant;
+
+        const KERNELS: [CountingKernel; 3] = [
+            CountingKernel::Rows,
+            CountingKernel::Scalar,
+            CountingKernel::Auto,
+        ];
+
+        /// Random rows with the nonce as target, nonce bit 3 depending on
+        /// header bits 1 and 5
+        fn rows(count: usize, header_bits: u16) -> Vec<BlockHeaderData> {
+            let planted = parse_planted("3=1^5@0.9").unwrap();
+            let mut rows = generate_dataset(count, header_bits, 8, &[planted], 7).unwrap();
+            for row in rows.iter_mut() {
+                row.target = row.nonce.clone();
+            }
+            rows
+        }
+
+        /// Header tuples of one to three bits, each with single, joint and
+        /// wider target bits
+        fn addresses(mode: ScoringMode) -> Vec<Statistic> {
+            let mut stats = vec![];
+            for header in [vec![2], vec![1, 5], vec![0, 7, 23]] {
+                for target in [vec![3], vec![0, 1], vec![2, 4, 6]] {
+                    account_address(&header, &target, mode, &mut stats);
+                }
+            }
+            stats
+        }
+
+        fn counts(data: &Dataset, mode: ScoringMode, kernel: CountingKernel) -> Vec<Vec<u32>> {
+            let mut stats = addresses(mode);
+            count_statistics(data, &mut stats, mode, kernel).unwrap();
+            stats.into_iter().map(|s| s.counts).collect()
+        }
+
+        /// A packed file of the rows, mapped with the nonce as target
+        fn packed(rows: &[BlockHeaderData], name: &str) -> Dataset {
+            let path = std::env::temp_dir().join(format!("{}-{}.btp", name, std::process::id()));
+            let path = path.to_str().unwrap();
+            save_packed_file(path, rows, "rows", "").unwrap();
+            let preparation = Preparation {
+                validation: None,
+                features: false,
+                target: Target::Nonce,
+            };
+            let (data, _) = open_dataset(path, InputFormat::Bin, None, preparation).unwrap();
+            // The mapping outlives the file name
+            std::fs::remove_file(path).unwrap();
+            assert!(matches!(data, Dataset::Packed(_)));
+            data
+        }
+
+        #[test]
+        fn kernels_count_the_same() {
+            // Not a multiple of 64 rows, the last word is partly used
+            let data = Dataset::Memory(rows(1000, 24));
+            for mode in [ScoringMode::Table, ScoringMode::Parity] {
+                let expected = counts(&data, mode, CountingKernel::Rows);
+                for s in &expected {
+                    assert_eq!(s.iter().sum::<u32>(), 1000);
+                }
+                for kernel in KERNELS {
+                    assert_eq!(
+                        counts(&data, mode, kernel),
+                        expected,
+                        "{:?} {:?}",
+                        mode,
+                        kernel
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn popcounts_match_the_scalar_count() {
+            let mut rng = Rng::new(5);
+            // Whole vectors and every length of the tail, all ones too
+            for words in 0..40 {
+                let a: Vec<u64> = (0..words).map(|_| rng.next_u64()).collect();
+                let b: Vec<u64> = (0..words).map(|_| rng.next_u64()).collect();
+                let ones = vec![u64::MAX; words];
+                for (a, b) in [(&a, &b), (&ones, &ones), (&a, &ones)] {
+                    let expected = and_count_scalar(a, b);
+                    assert_eq!(
+                        (Popcount::select(CountingKernel::Auto).and_count)(a, b),
+                        expected
+                    );
+                    #[cfg(target_arch = "x86_64")]
+                    {
+                        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("popcnt") {
+                            assert_eq!(x86::and_count_avx2(a, b), expected, "{} words", words);
+                        }
+                        if is_x86_feature_detected!("popcnt") {
+                            assert_eq!(x86::and_count_popcnt(a, b), expected, "{} words", words);
+                        }
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn packed_columns_count_like_rows() {
+            // More than a block, the last one partly used
+            let rows = rows(BLOCK_ROWS + 1000, 24);
+            let packed = packed(&rows, "bt-kernels");
+            let memory = Dataset::Memory(rows);
+            for mode in [ScoringMode::Table, ScoringMode::Parity] {
+                let expected = counts(&memory, mode, CountingKernel::Rows);
+                for kernel in KERNELS {
+                    assert_eq!(
+                        counts(&packed, mode, kernel),
+                        expected,
+                        "{:?} {:?}",
+                        mode,
+                        kernel
+                    );
+                }
+            }
+        }
+
+        fn instances(counts: &[u32], target_entropy: f32) -> Vec<(u32, DataInstance)> {
+            counts
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(key, counts)| (key as u32, data_instance(counts, &target_entropy)))
+                .collect()
+        }
+
+        #[test]
+        fn mutual_information_ignores_the_prior() {
+            // The target is balanced in the rows, a prior of 0.5 bits does
+            // not change the 1 - H(0.25) bits the header tells about it
+            let dependent = instances(&[30, 10, 10, 30], 0.5);
+            let mi = mutual_information(&dependent, 2, 80);
+            assert!((mi - 0.188_721_9).abs() < 1e-6, "{}", mi);
+            // The information of an instance is still against the prior
+            assert!((dependent[0].1.information - (0.5 - 0.811_278_1)).abs() < 1e-6);
+
+            // Skewed but independent of the header, nothing to tell whatever
+            // the prior
+            for prior in [0.0, 0.2, 1.0] {
+                let independent = instances(&[18, 2, 9, 1], prior);
+                assert_eq!(mutual_information(&independent, 2, 30), 0.0);
+            }
+        }
+
+        #[test]
+        fn g_test_uses_the_counts() {
+            // G by hand: E is 20 in every cell, 2 (60 ln 1.5 + 20 ln 0.5)
+            assert!((g_statistic(&[30, 10, 10, 30], 2) - 20.929_926).abs() < 1e-5);
+            assert_eq!(g_statistic(&[18, 2, 9, 1], 2), 0.0);
+
+            // A rare target bit, far from a prior of p1 = 0.43: the p-value
+            // only depends on the G of the counts
+            let counts = vec![327, 12, 160, 14, 302, 20, 144, 21];
+            let g = 14.789_443;
+            let mut stats = vec![];
+            account_address(&[544, 563], &[0], ScoringMode::Table, &mut stats);
+            let mut s = stats.pop().unwrap();
+            s.counts = counts;
+            let scoring = Scoring {
+                mode: ScoringMode::Table,
+                kernel: CountingKernel::Auto,
+                target_entropies: HashMap::from([(vec![0], entropy(&[0.57, 0.43]))]),
+                sample_threshold: 0,
+                info_threshold: 0.0,
+                max_p_value: 1.0,
+                top: Some(TopK {
+                    by: TopScore::PValue,
+                    global: Some(1),
+                    per_target: None,
+                }),
             };
-            address_stat.instances.insert(k, new_data_instance);
+            let s = finish_statistic(s, 1000, &scoring).unwrap().unwrap();
+            let p_value = s.score.unwrap();
+            let expected = chi_square_p_value(g, 3.0);
+            assert!((p_value / expected - 1.0).abs() < 1e-4, "{}", p_value);
+            assert!(p_value > 1e-3 && p_value < 1e-2, "{}", p_value);
+            // The same G from the mutual information in bits
+            let g_from_mi = 2.0 * LN_2 * 1000.0 * s.mutual_information as f64;
+            assert!((g_from_mi - g).abs() < 1e-2, "{}", g_from_mi);
+        }
+
+        /// Time per address of each kernel, on rows in memory and packed:
+        /// `cargo test --release -- --ignored --nocapture bench_kernels`
+        #[test]
+        #[ignore]
+        fn bench_kernels() {
+            let rows = rows(200_000, 640);
+            let mut stats = vec![];
+            for a in 0..48 {
+                for b in a + 1..48 {
+                    for target in 0..8 {
+                        account_address(&[a, b], &[target], ScoringMode::Table, &mut stats);
+                    }
+                }
+            }
+            let packed = packed(&rows, "bt-bench");
+            let memory = Dataset::Memory(rows);
+            for (name, data) in [("memory", &memory), ("packed", &packed)] {
+                for kernel in KERNELS {
+                    let mut stats = stats.clone();
+                    let start = Instant::now();
+                    count_statistics(data, &mut stats, ScoringMode::Table, kernel).unwrap();
+                    println!(
+                        "{} {}: {:.4} ms per address",
+                        name,
+                        kernel_name(kernel),
+                        start.elapsed().as_secs_f64() * 1000.0 / stats.len() as f64
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub mod selection {
+    use crate::data_structures::data_structures::ScoringMode;
+    use crate::dataset::dataset::Dataset;
+    use crate::errors::errors::Error;
+    use crate::statistics::computation::{bits_parity, bits_value, entropy};
+
+    /// Marginal entropy of every header bit across the whole dataset, from the
+    /// number of ones of each bit out of `rows`.
+    pub fn header_bit_entropies(ones: &[u64], rows: u64) -> Vec<f32> {
+        let total = rows as f32;
+        ones.iter()
+            .map(|count| {
+                let p_one = *count as f32 / total;
+                entropy(&[1.0 - p_one, p_one])
+            })
+            .collect()
+    }
+
+    /// Entropy of each group of target bits across the whole dataset, joint
+    /// entropy in table mode and entropy of the XOR parity in parity mode, all
+    /// counted in one pass.
+    pub fn measure_target_entropies(
+        data: &Dataset,
+        groups: &[Vec<u16>],
+        mode: ScoringMode,
+    ) -> Result<Vec<f32>, Error> {
+        let mut counts: Vec<Vec<u32>> = groups
+            .iter()
+            .map(|group| match mode {
+                ScoringMode::Table => vec![0; 1 << group.len()],
+                ScoringMode::Parity => vec![0; 2],
+            })
+            .collect();
+        let mut samples = 0;
+        if let Dataset::Packed(packed) = data {
+            // The target bits of a row are read from their columns, in the
+            // order of `bits_value`
+            for (group, counts) in groups.iter().zip(counts.iter_mut()) {
+                let columns: Vec<&[u64]> = group.iter().map(|b| packed.target_column(*b)).collect();
+                for row in 0..packed.rows() {
+                    let (word, shift) = (row / 64, row % 64);
+                    let value = columns.iter().fold(0u32, |acc, column| {
+                        let bit = (column[word] >> shift) as u32 & 1;
+                        match mode {
+                            ScoringMode::Table => (acc << 1) | bit,
+                            ScoringMode::Parity => acc ^ bit,
+                        }
+                    });
+                    counts[value as usize] += 1;
+                }
+            }
+            samples = packed.rows();
+        } else {
+            data.for_each_chunk(|rows| {
+                for (group, counts) in groups.iter().zip(counts.iter_mut()) {
+                    for entry in rows {
+                        let value = match mode {
+                            ScoringMode::Table => bits_value(&entry.target, group),
+                            ScoringMode::Parity => bits_parity(&entry.target, group),
+                        };
+                        counts[value as usize] += 1;
+                    }
+                }
+                samples += rows.len();
+                Ok(())
+            })?;
+        }
+        Ok(counts
+            .iter()
+            .map(|counts| probabilities_entropy(counts, samples))
+            .collect())
+    }
+
+    fn probabilities_entropy(counts: &[u32], samples: usize) -> f32 {
+        let probabilities: Vec<f32> = counts.iter().map(|c| *c as f32 / samples as f32).collect();
+        entropy(&probabilities)
+    }
+
+    /// Splits the header bits from `start_bit` onwards that pass the include and
+    /// exclude masks into the ones worth combining into tuples and the ones whose
+    /// entropy is at or below `min_entropy`. Returns (selected, excluded).
+    pub fn select_header_bits(
+        entropies: &[f32],
+        start_bit: u16,
+        min_entropy: f32,
+        include: Option<&[u16]>,
+        exclude: &[u16],
+    ) -> (Vec<u16>, Vec<u16>) {
+        let mut selected = vec![];
+        let mut excluded = vec![];
+        for (bit, entropy) in entropies.iter().enumerate().skip(start_bit as usize) {
+            let bit_u16 = bit as u16;
+            let masked = match include {
+                Some(bits) => !bits.contains(&bit_u16),
+                None => false,
+            } || exclude.contains(&bit_u16);
+            if masked {
+                continue;
+            }
+            if *entropy > min_entropy {
+                selected.push(bit as u16);
+            } else {
+                excluded.push(bit as u16);
+            }
+        }
+        (selected, excluded)
+    }
+}
+
+pub mod combinatorics {
+    use crate::data_structures::data_structures::DataAddress;
+    use std::ops::Range;
+
+    /// Number of ways to choose `k` items out of `n`, saturating at `u64::MAX`
+    pub fn binomial(n: u64, k: u64) -> u64 {
+        if k > n {
+            return 0;
+        }
+        let k = k.min(n - k);
+        let mut result: u128 = 1;
+        for i in 0..k {
+            // Exact at every step, result * (n - i) is a multiple of i + 1
+            result = result * (n - i) as u128 / (i + 1) as u128;
+            if result > u64::MAX as u128 {
+                return u64::MAX;
+            }
+        }
+        result as u64
+    }
+
+    /// Header tuples of `tuple_size` bits out of `header_bits`, in increasing
+    /// order, whose first bit is at most `max`, as the enumeration builds them
+    pub fn count_tuples(header_bits: &[u16], max: u16, tuple_size: u16) -> u64 {
+        if tuple_size == 0 {
+            return 1;
+        }
+        header_bits
+            .iter()
+            .enumerate()
+            .take_while(|(_, bit)| **bit <= max)
+            .map(|(i, _)| binomial((header_bits.len() - i - 1) as u64, tuple_size as u64 - 1))
+            .fold(0u64, |acc, c| acc.saturating_add(c))
+    }
+
+    /// The header tuples of a run, numbered by rank, so that a batch of work is
+    /// a range of ranks that the worker turns into addresses by itself
+    #[derive(Debug, Clone)]
+    pub enum TupleSpace {
+        /// The tuples counted by `count_tuples`, in lexicographic order, each
+        /// with every group of target bits
+        Combinations {
+            header_bits: Vec<u16>,
+            max: u16,
+            tuple_size: u16,
+            target_groups: Vec<Vec<u16>>,
+        },
+        /// The given addresses, one per rank
+        List(Vec<DataAddress>),
+    }
+
+    impl TupleSpace {
+        /// Number of header tuples, i.e. of ranks
+        pub fn count(&self) -> u64 {
+            match self {
+                TupleSpace::Combinations {
+                    header_bits,
+                    max,
+                    tuple_size,
+                    ..
+                } => count_tuples(header_bits, *max, *tuple_size),
+                TupleSpace::List(list) => list.len() as u64,
+            }
+        }
+
+        /// Addresses of each header tuple
+        pub fn per_tuple(&self) -> u64 {
+            match self {
+                TupleSpace::Combinations { target_groups, .. } => target_groups.len() as u64,
+                TupleSpace::List(_) => 1,
+            }
+        }
+
+        /// Header bits of the tuple of the given rank
+        pub fn tuple(&self, rank: u64) -> Vec<u16> {
+            match self {
+                TupleSpace::Combinations {
+                    header_bits,
+                    tuple_size,
+                    ..
+                } => unrank(header_bits.len(), *tuple_size as usize, rank)
+                    .iter()
+                    .map(|p| header_bits[*p])
+                    .collect(),
+                TupleSpace::List(list) => list[rank as usize].header_bits.clone(),
+            }
+        }
+
+        /// Calls `f` with the header and target bits of every address of the
+        /// tuples of the given ranks, in order
+        pub fn for_each_address(&self, ranks: Range<u64>, mut f: impl FnMut(&[u16], &[u16])) {
+            match self {
+                TupleSpace::Combinations {
+                    header_bits,
+                    tuple_size,
+                    target_groups,
+                    ..
+                } => {
+                    if ranks.is_empty() {
+                        return;
+                    }
+                    // Only the first tuple is unranked, the next ones follow
+                    let mut positions =
+                        unrank(header_bits.len(), *tuple_size as usize, ranks.start);
+                    let mut tuple: Vec<u16> = Vec::with_capacity(positions.len());
+                    for _ in ranks {
+                        tuple.clear();
+                        tuple.extend(positions.iter().map(|p| header_bits[*p]));
+                        for group in target_groups {
+                            f(&tuple, group);
+                        }
+                        next_combination(&mut positions, header_bits.len());
+                    }
+                }
+                TupleSpace::List(list) => {
+                    for address in &list[ranks.start as usize..ranks.end as usize] {
+                        f(&address.header_bits, &address.target_bits);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Positions of the combination of `k` out of `n` positions with the given
+    /// rank in lexicographic order, which has to be below `binomial(n, k)`
+    fn unrank(n: usize, k: usize, mut rank: u64) -> Vec<usize> {
+        let mut positions = Vec::with_capacity(k);
+        let mut next = 0;
+        for slot in 0..k {
+            // Skip the combinations that have a smaller position in this slot
+            loop {
+                let starting_here = binomial((n - next - 1) as u64, (k - slot - 1) as u64);
+                if rank < starting_here {
+                    break;
+                }
+                rank -= starting_here;
+                next += 1;
+            }
+            positions.push(next);
+            next += 1;
+        }
+        positions
+    }
+
+    /// Moves `positions` to the next combination of positions out of `n` in
+    /// lexicographic order, returns false after the last one
+    fn next_combination(positions: &mut [usize], n: usize) -> bool {
+        let k = positions.len();
+        for slot in (0..k).rev() {
+            if positions[slot] < n - k + slot {
+                positions[slot] += 1;
+                for next in slot + 1..k {
+                    positions[next] = positions[next - 1] + 1;
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        type Address = (Vec<u16>, Vec<u16>);
+
+        /// The recursive enumeration the tuple space replaced: every tuple in
+        /// increasing order, stopping at the first bit past `max`
+        fn unfold(
+            header_bits: &[u16],
+            max: u16,
+            target_groups: &[Vec<u16>],
+            curr: usize,
+            n: u16,
+            stack: &mut Vec<u16>,
+            addresses: &mut Vec<Address>,
+        ) {
+            if n == 0 {
+                for group in target_groups {
+                    addresses.push((stack.clone(), group.clone()));
+                }
+                return;
+            }
+            for i in curr..header_bits.len() {
+                let p = header_bits[i];
+                if stack.is_empty() && p > max {
+                    return;
+                }
+                stack.push(p);
+                unfold(
+                    header_bits,
+                    max,
+                    target_groups,
+                    i + 1,
+                    n - 1,
+                    stack,
+                    addresses,
+                );
+                stack.pop();
+            }
+        }
+
+        fn spaces() -> Vec<TupleSpace> {
+            let header_bits = vec![0, 1, 2, 5, 7, 8, 11, 12, 13, 20, 31];
+            let target_groups = vec![vec![0], vec![1, 2], vec![3]];
+            let mut spaces = vec![];
+            for tuple_size in 0..=4 {
+                for max in [0, 5, 6, 12, 31, 100] {
+                    spaces.push(TupleSpace::Combinations {
+                        header_bits: header_bits.clone(),
+                        max,
+                        tuple_size,
+                        target_groups: target_groups.clone(),
+                    });
+                }
+            }
+            spaces
+        }
+
+        fn addresses(space: &TupleSpace, ranks: Range<u64>) -> Vec<Address> {
+            let mut addresses = vec![];
+            space.for_each_address(ranks, |header, target| {
+                addresses.push((header.to_vec(), target.to_vec()))
+            });
+            addresses
+        }
+
+        #[test]
+        fn addresses_match_recursive_unfold() {
+            for space in spaces() {
+                let TupleSpace::Combinations {
+                    header_bits,
+                    max,
+                    tuple_size,
+                    target_groups,
+                } = &space
+                else {
+                    unreachable!()
+                };
+                let mut expected = vec![];
+                let mut stack = vec![];
+                unfold(
+                    header_bits,
+                    *max,
+                    target_groups,
+                    0,
+                    *tuple_size,
+                    &mut stack,
+                    &mut expected,
+                );
+                assert_eq!(
+                    space.count() * space.per_tuple(),
+                    expected.len() as u64,
+                    "{:?}",
+                    space
+                );
+                assert_eq!(addresses(&space, 0..space.count()), expected, "{:?}", space);
+            }
+        }
+
+        #[test]
+        fn batches_continue_the_sequence() {
+            for space in spaces() {
+                let all = addresses(&space, 0..space.count());
+                let per_tuple = space.per_tuple() as usize;
+                for batch in [1, 2, 7, 40] {
+                    let mut joined = vec![];
+                    for start in (0..space.count()).step_by(batch) {
+                        let end = (start + batch as u64).min(space.count());
+                        // The first tuple of a batch is unranked, not iterated to
+                        assert_eq!(space.tuple(start), all[start as usize * per_tuple].0);
+                        joined.extend(addresses(&space, start..end));
+                    }
+                    assert_eq!(joined, all, "{:?} in batches of {}", space, batch);
+                }
+            }
+        }
+    }
+}
+
+pub mod ranking {
+    //! Top-K mode: instead of the statistics that pass the thresholds, a run
+    //! keeps the K best ones by their score, over all of them and for each
+    //! group of target bits. Each worker keeps the best of its batch, the
+    //! output sink the best of the batches, in heaps of at most K statistics.
+    use crate::data_structures::data_structures::{Statistic, TopK, TopScore};
+    use std::cmp::Ordering;
+    use std::collections::{BinaryHeap, HashMap};
+
+    /// A statistic in a heap, the worst one on top. Equal scores are ordered
+    /// by address, so that the best ones do not depend on the order in which
+    /// the batches finish.
+    struct Ranked {
+        // Higher is better
+        key: f64,
+        stat: Statistic,
+    }
+
+    impl Ord for Ranked {
+        fn cmp(&self, other: &Self) -> Ordering {
+            other.key.total_cmp(&self.key).then_with(|| {
+                (
+                    &self.stat.address.header_bits,
+                    &self.stat.address.target_bits,
+                )
+                    .cmp(&(
+                        &other.stat.address.header_bits,
+                        &other.stat.address.target_bits,
+                    ))
+            })
+        }
+    }
+
+    impl PartialOrd for Ranked {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl PartialEq for Ranked {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for Ranked {}
+
+    /// The `k` best statistics pushed so far
+    struct Best {
+        k: usize,
+        heap: BinaryHeap<Ranked>,
+    }
+
+    impl Best {
+        fn new(k: usize) -> Best {
+            Best {
+                k,
+                heap: BinaryHeap::with_capacity(k + 1),
+            }
+        }
+
+        fn push(&mut self, ranked: Ranked) {
+            self.heap.push(ranked);
+            if self.heap.len() > self.k {
+                self.heap.pop();
+            }
+        }
+
+        /// Best first
+        fn into_sorted(self) -> Vec<Statistic> {
+            self.heap
+                .into_sorted_vec()
+                .into_iter()
+                .map(|ranked| ranked.stat)
+                .collect()
+        }
+    }
+
+    impl std::fmt::Debug for Best {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Best({} of {})", self.heap.len(), self.k)
         }
+    }
 
-        stats.push(address_stat);
+    /// The best statistics of a batch or of a run
+    #[derive(Debug)]
+    pub struct TopResults {
+        by: TopScore,
+        global: Option<Best>,
+        per_target_k: Option<usize>,
+        per_target: HashMap<Vec<u16>, Best>,
     }
 
-    fn update_statistics(values: &mut HashMap<u32, DataInstance>, nonce_bit_entropy: &f32) {
-        for (_, entry) in values.iter_mut() {
-            entry.total = entry.ones + entry.zeros;
-            if entry.total > 0 {
-                entry.p_zero = entry.zeros as f32 / entry.total as f32;
-                entry.p_one = entry.ones as f32 / entry.total as f32;
+    impl TopResults {
+        pub fn new(top: &TopK) -> TopResults {
+            TopResults {
+                by: top.by,
+                global: top.global.map(Best::new),
+                per_target_k: top.per_target,
+                per_target: HashMap::new(),
+            }
+        }
+
+        /// Adds scored statistics, keeping the best ones
+        pub fn add(&mut self, stats: Vec<Statistic>) {
+            for stat in stats {
+                self.push(Ranked {
+                    key: ranking_key(&stat, self.by),
+                    stat,
+                });
             }
-            // Compute entropy and information
-            let mut entropy_xy: f32 = 0.0;
+        }
 
-            if entry.p_zero > 0.0 {
-                entropy_xy = entropy_xy + entry.p_zero * entry.p_zero.log2();
+        /// Adds the best statistics of another batch
+        pub fn merge(&mut self, other: TopResults) {
+            if let (Some(best), Some(other)) = (&mut self.global, other.global) {
+                for ranked in other.heap {
+                    best.push(ranked);
+                }
             }
-            if entry.p_one > 0.0 {
-                entropy_xy = entropy_xy + entry.p_one * entry.p_one.log2();
+            for ranked in other.per_target.into_values().flat_map(|best| best.heap) {
+                self.push_per_target(ranked);
             }
-            entropy_xy = (-1.0) * entropy_xy;
-            entry.entropy = entropy_xy;
-            entry.information = nonce_bit_entropy - entropy_xy;
         }
+
+        fn push(&mut self, ranked: Ranked) {
+            match &mut self.global {
+                Some(best) if self.per_target_k.is_some() => {
+                    best.push(Ranked {
+                        key: ranked.key,
+                        stat: ranked.stat.clone(),
+                    });
+                    self.push_per_target(ranked);
+                }
+                Some(best) => best.push(ranked),
+                None => self.push_per_target(ranked),
+            }
+        }
+
+        fn push_per_target(&mut self, ranked: Ranked) {
+            if let Some(k) = self.per_target_k {
+                self.per_target
+                    .entry(ranked.stat.address.target_bits.clone())
+                    .or_insert_with(|| Best::new(k))
+                    .push(ranked);
+            }
+        }
+
+        /// The best statistics over all of them and, one group of target bits
+        /// after the other, of each group, best first
+        pub fn into_sorted(self) -> (Option<Vec<Statistic>>, Option<Vec<Statistic>>) {
+            let TopResults {
+                global,
+                per_target_k,
+                per_target,
+                ..
+            } = self;
+            let global = global.map(Best::into_sorted);
+            let per_target = per_target_k.map(|_| {
+                let mut groups: Vec<(Vec<u16>, Best)> = per_target.into_iter().collect();
+                groups.sort_by(|a, b| a.0.cmp(&b.0));
+                groups
+                    .into_iter()
+                    .flat_map(|(_, best)| best.into_sorted())
+                    .collect()
+            });
+            (global, per_target)
+        }
+    }
+
+    /// Higher is better, whatever the score
+    fn ranking_key(stat: &Statistic, by: TopScore) -> f64 {
+        let score = stat.score.unwrap_or(f64::NAN);
+        let key = match by {
+            TopScore::PValue => -score,
+            TopScore::MutualInformation | TopScore::Information => score,
+        };
+        if key.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            key
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::data_structures::data_structures::ScoringMode;
+        use crate::statistics::computation::account_address;
+
+        type Entry = (Vec<u16>, Vec<u16>, f64);
+
+        /// Statistics of header pairs against three target groups, with few
+        /// distinct scores so that many of them tie
+        fn stats() -> Vec<Statistic> {
+            let mut stats = vec![];
+            for a in 0..12u16 {
+                for b in a + 1..12 {
+                    for target in [vec![0], vec![1, 2], vec![3]] {
+                        account_address(&[a, b], &target, ScoringMode::Table, &mut stats);
+                    }
+                }
+            }
+            for (i, s) in stats.iter_mut().enumerate() {
+                s.score = Some(((i * 7919) % 5) as f64 / 100.0);
+            }
+            stats
+        }
+
+        fn entries(stats: &[Statistic]) -> Vec<Entry> {
+            stats
+                .iter()
+                .map(|s| {
+                    (
+                        s.address.header_bits.clone(),
+                        s.address.target_bits.clone(),
+                        s.score.unwrap(),
+                    )
+                })
+                .collect()
+        }
+
+        /// The `k` best by one sort of them all: best score first, then the
+        /// lowest address
+        fn sorted_best(stats: &[Statistic], by: TopScore, k: usize) -> Vec<Entry> {
+            let mut all = entries(stats);
+            all.sort_by(|a, b| {
+                let (a_key, b_key) = match by {
+                    TopScore::PValue => (-a.2, -b.2),
+                    _ => (a.2, b.2),
+                };
+                b_key
+                    .total_cmp(&a_key)
+                    .then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1)))
+            });
+            all.truncate(k);
+            all
+        }
+
+        /// The best of each batch, as a worker keeps them
+        fn batch_results(stats: &[Statistic], top: &TopK, batch: usize) -> Vec<TopResults> {
+            stats
+                .chunks(batch)
+                .map(|chunk| {
+                    let mut results = TopResults::new(top);
+                    results.add(chunk.to_vec());
+                    results
+                })
+                .collect()
+        }
+
+        #[test]
+        fn merged_batches_keep_the_best_of_a_global_sort() {
+            let stats = stats();
+            for by in [TopScore::MutualInformation, TopScore::PValue] {
+                for k in [1, 5, 17, 1000] {
+                    let top = TopK {
+                        by,
+                        global: Some(k),
+                        per_target: Some(k),
+                    };
+                    let expected_global = sorted_best(&stats, by, k);
+                    let mut expected_per_target = vec![];
+                    for target in [vec![0], vec![1, 2], vec![3]] {
+                        let group: Vec<Statistic> = stats
+                            .iter()
+                            .filter(|s| s.address.target_bits == target)
+                            .cloned()
+                            .collect();
+                        expected_per_target.extend(sorted_best(&group, by, k));
+                    }
+
+                    // Batches of any size, their results merged in any order,
+                    // as they come from the workers
+                    for batch in [1, 7, 64, stats.len()] {
+                        for reverse in [false, true] {
+                            let mut results = batch_results(&stats, &top, batch);
+                            if reverse {
+                                results.reverse();
+                            }
+                            let mut sink = TopResults::new(&top);
+                            for result in results {
+                                sink.merge(result);
+                            }
+                            let (global, per_target) = sink.into_sorted();
+                            let context = format!("{:?} k {} batch {}", by, k, batch);
+                            assert_eq!(entries(&global.unwrap()), expected_global, "{}", context);
+                            assert_eq!(
+                                entries(&per_target.unwrap()),
+                                expected_per_target,
+                                "{}",
+                                context
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub mod progress {
+    use crate::data_structures::data_structures::BatchReport;
+    use crate::errors::errors::Error;
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    /// Reports how far a run is, from the batches the workers finish: percent
+    /// done, addresses per second, how busy each worker was and the time left.
+    /// Besides printing, each report can be appended to a file as a JSON line.
+    pub struct ProgressReporter {
+        // Addresses of the whole run, including the ones of a run resumed from
+        total: u64,
+        done_before: u64,
+        scored: u64,
+        busy: Vec<Duration>,
+        start: Instant,
+        last_report: Instant,
+        interval: Duration,
+        file: Option<(String, File)>,
+    }
+
+    impl ProgressReporter {
+        pub fn new(
+            total: u64,
+            done_before: u64,
+            workers: usize,
+            interval: Duration,
+            file_name: Option<String>,
+        ) -> Result<ProgressReporter, Error> {
+            let file = match file_name {
+                Some(name) => {
+                    let file = File::create(&name).map_err(|e| Error::Export(name.clone(), e))?;
+                    Some((name, file))
+                }
+                None => None,
+            };
+            let now = Instant::now();
+            Ok(ProgressReporter {
+                total,
+                done_before,
+                scored: 0,
+                busy: vec![Duration::ZERO; workers],
+                start: now,
+                last_report: now,
+                interval,
+                file,
+            })
+        }
+
+        /// Counts a finished batch, and reports when the interval has passed
+        pub fn record(&mut self, batch: &BatchReport) -> Result<(), Error> {
+            self.scored += batch.scored as u64;
+            if let Some(busy) = self.busy.get_mut(batch.worker) {
+                *busy += batch.busy;
+            }
+            if self.last_report.elapsed() >= self.interval {
+                self.report()?;
+            }
+            Ok(())
+        }
+
+        pub fn report(&mut self) -> Result<(), Error> {
+            self.last_report = Instant::now();
+            let elapsed = self.start.elapsed().as_secs_f64();
+            let done = self.done_before + self.scored;
+            let percent = if self.total > 0 {
+                100.0 * done as f64 / self.total as f64
+            } else {
+                100.0
+            };
+            let rate = if elapsed > 0.0 {
+                self.scored as f64 / elapsed
+            } else {
+                0.0
+            };
+            let eta = if rate > 0.0 {
+                Some(self.total.saturating_sub(done) as f64 / rate)
+            } else {
+                None
+            };
+            let utilisation: Vec<f64> = self
+                .busy
+                .iter()
+                .map(|b| {
+                    if elapsed > 0.0 {
+                        b.as_secs_f64() / elapsed
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+
+            let busy: Vec<String> = utilisation
+                .iter()
+                .map(|u| format!("{:.0}%", 100.0 * u))
+                .collect();
+            println!(
+                "Progress: {:.2}% ({}/{} addresses), {:.1} addresses/s, ETA {}, workers busy {}",
+                percent,
+                done,
+                self.total,
+                rate,
+                eta.map(format_duration)
+                    .unwrap_or_else(|| "unknown".to_string()),
+                busy.join(" ")
+            );
+
+            if let Some((name, file)) = &mut self.file {
+                let line = json::object! {
+                    elapsed_secs: elapsed,
+                    done: done,
+                    total: self.total,
+                    percent: percent,
+                    addresses_per_sec: rate,
+                    eta_secs: eta,
+                    utilisation: utilisation,
+                };
+                writeln!(file, "{}", json::stringify(line))
+                    .map_err(|e| Error::Export(name.clone(), e))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// e.g. `1h02m03s`
+    pub fn format_duration(secs: f64) -> String {
+        let secs = secs.round() as u64;
+        format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+pub mod estimate {
+    use crate::data_structures::data_structures::{DataAddress, Scoring, ScoringMode, Statistic};
+    use crate::dataset::dataset::Dataset;
+    use crate::errors::errors::Error;
+    use crate::statistics::computation::{account_address, score_statistics};
+    use std::mem::size_of;
+    use std::time::{Duration, Instant};
+
+    /// Addresses in each batch handed to a worker. The pending statistics go
+    /// once there are more than `slice_size` of them, checked before each
+    /// header tuple adds its `per_tuple` addresses. Only the last batch is smaller.
+    pub fn batch_size(slice_size: usize, per_tuple: u64) -> u64 {
+        let per_tuple = per_tuple.max(1);
+        (slice_size as u64 / per_tuple + 1) * per_tuple
+    }
+
+    /// Approximate heap and inline bytes of a statistic once counted, with
+    /// `2^header_len` instances (2 in parity mode) of `2^target_len` counts
+    pub fn statistic_bytes(header_len: usize, target_len: usize, mode: ScoringMode) -> u64 {
+        let (instances, target_values): (u64, u64) = match mode {
+            ScoringMode::Table => (1 << header_len, 1 << target_len),
+            ScoringMode::Parity => (2, 2),
+        };
+        // The counts are 4 bytes each, the bits 2
+        let counts = instances * 4 * target_values;
+        let bits = 2 * (header_len + target_len) as u64;
+        size_of::<Statistic>() as u64 + bits + counts
+    }
+
+    /// Seconds a worker takes to score one address, measured by scoring the
+    /// sample addresses over and over for about `duration`
+    pub fn calibrate(
+        data: &Dataset,
+        sample: &[DataAddress],
+        scoring: &Scoring,
+        duration: Duration,
+    ) -> Result<f64, Error> {
+        if sample.is_empty() {
+            return Ok(0.0);
+        }
+        let start = Instant::now();
+        let mut scored: u64 = 0;
+        while scored == 0 || start.elapsed() < duration {
+            let mut stats = vec![];
+            for address in sample {
+                account_address(
+                    &address.header_bits,
+                    &address.target_bits,
+                    scoring.mode,
+                    &mut stats,
+                );
+            }
+            score_statistics(data, stats, scoring)?;
+            scored += sample.len() as u64;
+        }
+        Ok(start.elapsed().as_secs_f64() / scored as f64)
+    }
+
+    /// e.g. `1.5 GiB`
+    pub fn format_bytes(bytes: u64) -> String {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < units.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        format!("{:.1} {}", value, units[unit])
     }
 }